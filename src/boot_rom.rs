@@ -0,0 +1,41 @@
+use crate::error::{self, EmuError};
+
+const DMG_BOOT_ROM_SIZE: usize = 0x100;
+const CGB_BOOT_ROM_SIZE: usize = 0x900;
+
+// The boot ROM is overlaid on the start of the cartridge ROM until it unmaps itself by writing to
+// 0xFF50. The CGB one is larger and also covers 0x0200-0x08FF, leaving the cartridge header at
+// 0x0100-0x01FF visible.
+pub struct BootRom {
+    data: Vec<u8>,
+}
+
+impl BootRom {
+    pub fn new(data: &[u8]) -> Result<BootRom, EmuError> {
+        if data.len() != DMG_BOOT_ROM_SIZE && data.len() != CGB_BOOT_ROM_SIZE {
+            return Err(EmuError::InvalidBootRomSize { size: data.len() });
+        }
+        Ok(BootRom {
+            data: data.to_vec(),
+        })
+    }
+
+    pub fn from_file(path: &str) -> Result<BootRom, EmuError> {
+        BootRom::new(&error::read_file(path)?)
+    }
+
+    pub fn is_cgb(&self) -> bool {
+        self.data.len() == CGB_BOOT_ROM_SIZE
+    }
+
+    // The byte at the address, if the boot ROM covers it.
+    pub fn read(&self, address: u16) -> Option<u8> {
+        match address {
+            0x0000..=0x00ff => Some(self.data[address as usize]),
+            0x0200..=0x08ff if self.data.len() == CGB_BOOT_ROM_SIZE => {
+                Some(self.data[address as usize])
+            }
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,284 @@
+pub mod detect;
+pub mod eeprom;
+pub mod header;
+pub mod huc1;
+pub mod huc3;
+pub mod m161;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+pub mod mbc7;
+pub mod mmm01;
+pub mod pocket_camera;
+pub mod rom_only;
+pub mod rtc;
+pub mod sachen;
+pub mod state;
+pub mod wisdom_tree;
+
+use std::rc::Rc;
+
+use crate::boot_rom::BootRom;
+use crate::error::EmuError;
+use crate::infrared::Infrared;
+use header::{CartridgeHeader, Mapper};
+use huc1::HuC1;
+use huc3::HuC3;
+use m161::M161;
+use mbc1::MBC1;
+use mbc2::MBC2;
+use mbc3::MBC3;
+use mbc5::MBC5;
+use mbc7::MBC7;
+use mmm01::MMM01;
+use pocket_camera::image::ImageSource;
+use pocket_camera::PocketCamera;
+use rom_only::RomOnly;
+use rtc::RtcMode;
+use sachen::Sachen;
+use wisdom_tree::WisdomTree;
+
+// The game pak. The bus routes ROM at 0x0000-0x7FFF and external RAM at 0xA000-0xBFFF through
+// it, so everything a mapper does happens behind these hooks. The mappers in this module
+// implement it, and custom hardware can too from outside the crate, to be plugged in with
+// `CPU::insert_cartridge`. Such a mapper only has to provide the ROM and RAM accesses and the
+// state methods, plus the save methods when it has a battery. Everything else has a default for
+// cartridges without the hardware in question.
+pub trait Cartridge {
+    fn read_rom(&self, address: u16) -> u8;
+    // Writes to ROM are how mappers are controlled.
+    fn write_rom(&mut self, address: u16, value: u8);
+    fn read_ram(&self, address: u16) -> u8;
+    fn write_ram(&mut self, address: u16, value: u8);
+
+    // Advances anything on the cartridge that keeps time, by T-cycles of the base clock. CGB
+    // double speed has already been accounted for.
+    fn tick(&mut self, _cycles: u32) {}
+
+    // What goes in the save file, missing when nothing survives power off.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    fn load_save_data(&mut self, _data: &[u8]) {}
+
+    // Whether the save data changed since the last call. The save file is only rewritten after
+    // this reports a change, so it should cover battery RAM, EEPROM and clock settings being
+    // written, but not register writes or the clock ticking.
+    fn take_save_dirty(&mut self) -> bool {
+        false
+    }
+
+    // Everything but the ROM, to resume exactly where the cartridge left off.
+    fn save_state(&self) -> Vec<u8>;
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError>;
+
+    // Optional hardware: the motor, speaker, sensors, clock and ports a few cartridges have.
+    // Only the cartridges with the hardware override these, and the bus calls them on every
+    // cartridge, so the defaults must stay harmless no-ops.
+
+    // Whether the rumble motor is running.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    // Whether the speaker is playing its tone.
+    fn tone(&self) -> bool {
+        false
+    }
+
+    // Feeds the tilt sensor, in g on each axis.
+    fn set_tilt(&mut self, _x: f32, _y: f32) {}
+
+    // Picks the time source of the real-time clock.
+    fn set_rtc_mode(&mut self, _mode: RtcMode) {}
+
+    // Tells the cartridge which boot ROM, if any, is mapped over it. Some unlicensed cartridges
+    // behave differently until the boot ROM is gone.
+    fn set_boot_rom(&mut self, _boot_rom: Option<&BootRom>) {}
+
+    // Connects the infrared port.
+    fn set_infrared(&mut self, _infrared: Rc<dyn Infrared>) {}
+
+    // Sets what the camera sensor sees.
+    fn set_image_source(&mut self, _image_source: Box<dyn ImageSource>) {}
+
+    // Writes the photos saved on a camera cartridge to `directory` and returns how many there
+    // were.
+    fn export_photos(&self, _directory: &str) -> Result<usize, EmuError> {
+        Ok(0)
+    }
+}
+
+// Builds the mapper a ROM needs, along with its header when it has one that parses. Most ROMs go
+// by their header. Multicarts and unlicensed cartridges whose headers lie are recognized from the
+// ROM contents first.
+pub fn from_rom(rom: &[u8]) -> Result<(Box<dyn Cartridge>, Option<CartridgeHeader>), EmuError> {
+    if let Some(mapper) = detect::detect_mapper(rom) {
+        return Ok(from_detected_mapper(mapper, rom));
+    }
+
+    let header = CartridgeHeader::parse(rom)?;
+    let rom = rom.to_vec();
+    let ram_size = header.ram_size;
+    let cartridge_type = &header.cartridge_type;
+    let battery = cartridge_type.battery;
+    let cartridge: Box<dyn Cartridge> = match cartridge_type.mapper {
+        Mapper::None => Box::new(RomOnly::new(rom, ram_size, battery)),
+        Mapper::MBC1 => Box::new(MBC1::new(rom, ram_size, battery)),
+        Mapper::MBC2 => Box::new(MBC2::new(rom, battery)),
+        Mapper::MMM01 => Box::new(MMM01::new(rom, ram_size, battery)),
+        Mapper::MBC3 => Box::new(MBC3::new(rom, ram_size, battery, cartridge_type.timer)),
+        Mapper::MBC5 => Box::new(MBC5::new(rom, ram_size, battery, cartridge_type.rumble)),
+        Mapper::MBC7 => Box::new(MBC7::new(rom)),
+        Mapper::HuC1 => Box::new(HuC1::new(rom, ram_size, battery)),
+        Mapper::HuC3 => Box::new(HuC3::new(rom, ram_size, battery)),
+        Mapper::PocketCamera => Box::new(PocketCamera::new(rom, ram_size, battery)),
+        _ => {
+            return Err(EmuError::UnsupportedCartridgeType {
+                cartridge_type: cartridge_type.code,
+            })
+        }
+    };
+    Ok((cartridge, Some(header)))
+}
+
+// The header is kept for what it's worth, but the mapper doesn't depend on it, except for the
+// MMM01 menu header saying how much RAM there is.
+fn from_detected_mapper(
+    mapper: Mapper,
+    rom: &[u8],
+) -> (Box<dyn Cartridge>, Option<CartridgeHeader>) {
+    let header_start = match mapper {
+        Mapper::MMM01 => detect::menu_header_offset(rom).unwrap_or(0),
+        _ => 0,
+    };
+    let header = CartridgeHeader::parse_lenient(&rom[header_start..]).ok();
+    let rom = rom.to_vec();
+    let cartridge: Box<dyn Cartridge> = match mapper {
+        Mapper::MMM01 => {
+            let (ram_size, battery) = header.as_ref().map_or((0, false), |header| {
+                (header.ram_size, header.cartridge_type.battery)
+            });
+            Box::new(MMM01::new(rom, ram_size, battery))
+        }
+        Mapper::M161 => Box::new(M161::new(rom)),
+        Mapper::SachenMMC1 => Box::new(Sachen::new(rom, false)),
+        Mapper::SachenMMC2 => Box::new(Sachen::new(rom, true)),
+        Mapper::WisdomTree => Box::new(WisdomTree::new(rom)),
+        _ => unreachable!(),
+    };
+    (cartridge, header)
+}
+
+// No cartridge inserted: the whole ROM area reads as 0xFF.
+pub fn empty() -> Box<dyn Cartridge> {
+    Box::new(RomOnly::new(vec![0xff; 0x8000], 0, false))
+}
+
+// Copies save data into RAM, leaving the rest of RAM alone when the data is short.
+fn load_ram(ram: &mut [u8], data: &[u8]) {
+    let length = ram.len().min(data.len());
+    ram[..length].copy_from_slice(&data[..length]);
+}
+
+// Writes a byte of RAM and returns whether that changed it.
+fn update_ram(ram: &mut [u8], offset: usize, value: u8) -> bool {
+    let changed = ram[offset] != value;
+    ram[offset] = value;
+    changed
+}
+
+// Offset into ROM of an address in a 16 KiB bank. Bank numbers past the end of the ROM wrap,
+// as the unused upper bank lines aren't connected.
+fn rom_offset(rom: &[u8], bank: usize, address: u16) -> usize {
+    (bank * 0x4000 + (address as usize & 0x3fff)) % rom.len()
+}
+
+// Offset into RAM of an address in an 8 KiB bank. RAM smaller than a bank is mirrored.
+fn ram_offset(ram: &[u8], bank: usize, address: u16) -> usize {
+    (bank * 0x2000 + (address as usize & 0x1fff)) % ram.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RAM_SIZE: usize = 0x800;
+
+    type NewCartridge = fn() -> Box<dyn Cartridge>;
+
+    fn cartridges() -> Vec<(&'static str, NewCartridge)> {
+        fn rom() -> Vec<u8> {
+            vec![0; 0x20000]
+        }
+        vec![
+            ("ROM only", || Box::new(RomOnly::new(rom(), RAM_SIZE, true))),
+            ("MBC1", || Box::new(MBC1::new(rom(), RAM_SIZE, true))),
+            ("MBC2", || Box::new(MBC2::new(rom(), true))),
+            ("MBC3", || Box::new(MBC3::new(rom(), RAM_SIZE, true, true))),
+            ("MBC5", || Box::new(MBC5::new(rom(), RAM_SIZE, true, true))),
+            ("MBC7", || Box::new(MBC7::new(rom()))),
+            ("HuC1", || Box::new(HuC1::new(rom(), RAM_SIZE, true))),
+            ("HuC3", || Box::new(HuC3::new(rom(), RAM_SIZE, true))),
+            ("Pocket Camera", || {
+                Box::new(PocketCamera::new(rom(), RAM_SIZE, true))
+            }),
+            ("MMM01", || Box::new(MMM01::new(rom(), RAM_SIZE, true))),
+            ("M161", || Box::new(M161::new(rom()))),
+            ("Sachen", || Box::new(Sachen::new(rom(), false))),
+            ("Wisdom Tree", || Box::new(WisdomTree::new(rom()))),
+        ]
+    }
+
+    // Puts a cartridge in a state that depends on the seed.
+    fn poke(cartridge: &mut dyn Cartridge, seed: u8) {
+        cartridge.set_rtc_mode(RtcMode::Deterministic);
+        cartridge.write_rom(0x0000, 0x0a);
+        cartridge.write_ram(0xa000 + seed as u16, seed);
+        // MBC2 takes the ROM bank where bit 8 of the address is set.
+        cartridge.write_rom(0x2100, seed);
+        cartridge.write_rom(0x4000, seed & 0x03);
+        cartridge.write_rom(0x6000, seed & 0x01);
+        // Wisdom Tree takes the ROM bank from the address.
+        cartridge.write_rom(seed as u16, 0x00);
+    }
+
+    #[test]
+    fn state_round_trips() {
+        for (name, new) in cartridges() {
+            let mut saved = new();
+            poke(saved.as_mut(), 5);
+            let state = saved.save_state();
+
+            let mut loaded = new();
+            poke(loaded.as_mut(), 6);
+            assert_ne!(loaded.save_state(), state, "{name}");
+            loaded.load_state(&state).unwrap();
+            assert_eq!(loaded.save_state(), state, "{name}");
+        }
+    }
+
+    #[test]
+    fn bad_state_leaves_the_cartridge_alone() {
+        for (name, new) in cartridges() {
+            let mut saved = new();
+            poke(saved.as_mut(), 5);
+            let state = saved.save_state();
+
+            let mut loaded = new();
+            poke(loaded.as_mut(), 6);
+            let before = loaded.save_state();
+            for length in 0..state.len() {
+                assert!(loaded.load_state(&state[..length]).is_err(), "{name}");
+                assert_eq!(loaded.save_state(), before, "{name}, {length} bytes");
+            }
+
+            let mut trailing = state.clone();
+            trailing.push(0);
+            assert!(loaded.load_state(&trailing).is_err(), "{name}");
+            assert_eq!(loaded.save_state(), before, "{name}, trailing byte");
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use super::header::{CartridgeType, Mapper, NINTENDO_LOGO};
+use super::sachen::scramble;
+
+const LOGO_START: usize = 0x104;
+const LOGO_END: usize = 0x134;
+// MMM01 multicarts keep the menu, and the header describing the whole cartridge, in the last
+// 32 KiB of ROM.
+const MENU_SIZE: usize = 0x8000;
+const M161_SIZE: usize = 0x40000;
+
+// Picks out mappers whose cartridge header doesn't tell the truth: multicarts whose header is
+// the first game's, and unlicensed cartridges that claim a plain ROM or hide their header behind
+// address scrambling. Returns None for ROMs that should go by their header.
+pub fn detect_mapper(rom: &[u8]) -> Option<Mapper> {
+    if rom.len() < 0x150 {
+        return None;
+    }
+    if is_sachen(rom) {
+        // Only MMC2 games are made to run on the CGB.
+        return Some(if rom[0x143] & 0x80 != 0 {
+            Mapper::SachenMMC2
+        } else {
+            Mapper::SachenMMC1
+        });
+    }
+    if menu_header_offset(rom).is_some() {
+        return Some(Mapper::MMM01);
+    }
+    if is_m161(rom) {
+        return Some(Mapper::M161);
+    }
+    if is_wisdom_tree(rom) {
+        return Some(Mapper::WisdomTree);
+    }
+    None
+}
+
+// Offset of the MMM01 menu, identified by a logo and an MMM01 type code in its header.
+pub fn menu_header_offset(rom: &[u8]) -> Option<usize> {
+    if rom.len() <= MENU_SIZE || !rom.len().is_power_of_two() {
+        return None;
+    }
+    let menu = rom.len() - MENU_SIZE;
+    let is_mmm01 = CartridgeType::from_code(rom[menu + 0x147])
+        .is_some_and(|cartridge_type| cartridge_type.mapper == Mapper::MMM01);
+    (is_mmm01 && has_logo(&rom[menu..])).then_some(menu)
+}
+
+// The logo only shows up where the boot ROM reads it through the scrambled address lines.
+fn is_sachen(rom: &[u8]) -> bool {
+    let scrambled_logo = (LOGO_START..LOGO_END).map(|i| rom[scramble(i as u16) as usize]);
+    !has_logo(rom) && scrambled_logo.eq(NINTENDO_LOGO)
+}
+
+// Mani 4 in 1 carts are 256 KiB, with each of the eight 32 KiB banks starting with a header of
+// its own. The first one claims an MBC3.
+fn is_m161(rom: &[u8]) -> bool {
+    rom.len() == M161_SIZE && rom.chunks(0x8000).all(has_logo)
+}
+
+// Wisdom Tree's games are larger than 32 KiB but say they are ROM only, often with a ROM size
+// of 32 KiB too. That alone also fits overdumps, so the first bank must carry the publisher's
+// name or a bank switch, `LD (0x00nn),A` for a bank other than 0.
+fn is_wisdom_tree(rom: &[u8]) -> bool {
+    if rom[0x147] != 0x00 || rom.len() <= 0x8000 {
+        return false;
+    }
+    let code = &rom[..0x8000];
+    // Written either "WISDOM TREE" or "WISDOM\0TREE".
+    let has_name = code.windows(11).any(|window| {
+        window.starts_with(b"WISDOM") && matches!(window[6], b' ' | 0) && window.ends_with(b"TREE")
+    });
+    let has_bank_switch = code
+        .windows(3)
+        .any(|window| window[0] == 0xea && window[1] != 0x00 && window[2] == 0x00);
+    has_name || has_bank_switch
+}
+
+fn has_logo(rom: &[u8]) -> bool {
+    rom[LOGO_START..LOGO_END] == NINTENDO_LOGO
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::boot_rom::BootRom;
+    use crate::cartridge::from_rom;
+    use crate::cartridge::sachen::scramble;
+
+    // A ROM with each 16 KiB bank starting with its number.
+    fn numbered_rom(size: usize) -> Vec<u8> {
+        let mut rom = vec![0; size];
+        for (bank, chunk) in rom.chunks_mut(0x4000).enumerate() {
+            chunk[0] = bank as u8;
+        }
+        rom
+    }
+
+    fn write_header(rom: &mut [u8], offset: usize, type_code: u8) {
+        rom[offset + LOGO_START..offset + LOGO_END].copy_from_slice(&NINTENDO_LOGO);
+        rom[offset + 0x147] = type_code;
+    }
+
+    #[test]
+    fn mmm01_maps_the_menu_until_a_game_is_chosen() {
+        let mut rom = numbered_rom(0x20000);
+        write_header(&mut rom, 0, 0x01);
+        write_header(&mut rom, 0x18000, 0x0b);
+        assert_eq!(detect_mapper(&rom), Some(Mapper::MMM01));
+
+        let (mut cartridge, _) = from_rom(&rom).unwrap();
+        assert_eq!(cartridge.read_rom(0x0000), 6);
+        assert_eq!(cartridge.read_rom(0x4000), 7);
+        cartridge.write_rom(0x2000, 0x02);
+        cartridge.write_rom(0x0000, 0x40);
+        assert_eq!(cartridge.read_rom(0x0000), 0);
+        assert_eq!(cartridge.read_rom(0x4000), 2);
+    }
+
+    #[test]
+    fn m161_latches_the_first_game_selected() {
+        let mut rom = numbered_rom(M161_SIZE);
+        write_header(&mut rom, 0, 0x10);
+        for game in 1..8 {
+            write_header(&mut rom, game * 0x8000, 0x00);
+        }
+        assert_eq!(detect_mapper(&rom), Some(Mapper::M161));
+
+        let (mut cartridge, _) = from_rom(&rom).unwrap();
+        cartridge.write_rom(0x4000, 0x03);
+        assert_eq!(cartridge.read_rom(0x0000), 6);
+        assert_eq!(cartridge.read_rom(0x4000), 7);
+        cartridge.write_rom(0x4000, 0x05);
+        assert_eq!(cartridge.read_rom(0x0000), 6);
+    }
+
+    #[test]
+    fn wisdom_tree_switches_on_the_address_written() {
+        let mut rom = numbered_rom(0x20000);
+        write_header(&mut rom, 0, 0x00);
+        assert_eq!(detect_mapper(&rom), None);
+        rom[0x150..0x153].copy_from_slice(&[0xea, 0x01, 0x00]);
+        assert_eq!(detect_mapper(&rom), Some(Mapper::WisdomTree));
+
+        let (mut cartridge, _) = from_rom(&rom).unwrap();
+        cartridge.write_rom(0x0001, 0x00);
+        assert_eq!(cartridge.read_rom(0x0000), 2);
+        assert_eq!(cartridge.read_rom(0x4000), 3);
+    }
+
+    #[test]
+    fn wisdom_tree_is_found_by_name() {
+        let mut rom = numbered_rom(0x20000);
+        write_header(&mut rom, 0, 0x00);
+        rom[0x200..0x20b].copy_from_slice(b"WISDOM\0TREE");
+        assert_eq!(detect_mapper(&rom), Some(Mapper::WisdomTree));
+    }
+
+    #[test]
+    fn sachen_scrambles_the_header_under_the_boot_rom() {
+        let mut rom = numbered_rom(0x20000);
+        for (i, byte) in (LOGO_START..LOGO_END).zip(NINTENDO_LOGO) {
+            rom[scramble(i as u16) as usize] = byte;
+        }
+        assert_eq!(detect_mapper(&rom), Some(Mapper::SachenMMC1));
+        rom[0x143] = 0x80;
+        assert_eq!(detect_mapper(&rom), Some(Mapper::SachenMMC2));
+
+        let (mut cartridge, _) = from_rom(&rom).unwrap();
+        let boot_rom = BootRom::new(&[0; 0x100]).unwrap();
+        cartridge.set_boot_rom(Some(&boot_rom));
+        let logo = (LOGO_START..LOGO_END).map(|i| cartridge.read_rom(i as u16));
+        assert!(logo.eq(NINTENDO_LOGO));
+        // The header's second half is scrambled too.
+        assert_eq!(cartridge.read_rom(0x0181), rom[0x01c0]);
+
+        cartridge.set_boot_rom(None);
+        assert_eq!(cartridge.read_rom(0x0181), rom[0x0181]);
+        cartridge.write_rom(0x2000, 0x02);
+        assert_eq!(cartridge.read_rom(0x4000), 2);
+    }
+}
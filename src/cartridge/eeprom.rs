@@ -0,0 +1,436 @@
+use super::state::{StateReader, StateWriter};
+use crate::error::EmuError;
+
+// 16-bit words in a 93LC56 configured for 16-bit organization.
+const WORDS: usize = 128;
+pub const SIZE: usize = WORDS * 2;
+
+// Bits clocked in after the start bit: a 2-bit opcode and an 8-bit address.
+const COMMAND_BITS: u8 = 10;
+
+enum State {
+    // Waiting for a 1 on DI as the start bit.
+    Idle,
+    Command {
+        bits: u16,
+        count: u8,
+    },
+    // Shifting out words, moving on to the next address after each one.
+    Reading {
+        address: u8,
+        data: u16,
+        remaining: u8,
+    },
+    // Shifting in a word for one address, or for all of them when there is none.
+    Writing {
+        address: Option<u8>,
+        data: u16,
+        count: u8,
+    },
+    // The command has completed. Further clocks are ignored until CS drops.
+    Done,
+}
+
+// A 93LC56 serial EEPROM, driven by bit-banging CS, CLK and DI and reading back DO. Words are
+// stored little-endian.
+pub struct Eeprom {
+    data: Vec<u8>,
+    state: State,
+    write_enabled: bool,
+    chip_select: bool,
+    clock: bool,
+    data_in: bool,
+    data_out: bool,
+    // Set when a write or erase changes the contents, until taken.
+    dirty: bool,
+}
+
+impl Eeprom {
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn load(&mut self, data: &[u8]) {
+        super::load_ram(&mut self.data, data);
+    }
+
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.dirty)
+    }
+
+    pub fn save_state(&self, state: &mut StateWriter) {
+        state.write_bytes(&self.data);
+        match self.state {
+            State::Idle => state.write_u8(0),
+            State::Command { bits, count } => {
+                state.write_u8(1);
+                state.write_u16(bits);
+                state.write_u8(count);
+            }
+            State::Reading {
+                address,
+                data,
+                remaining,
+            } => {
+                state.write_u8(2);
+                state.write_u8(address);
+                state.write_u16(data);
+                state.write_u8(remaining);
+            }
+            State::Writing {
+                address,
+                data,
+                count,
+            } => {
+                state.write_u8(3);
+                // 0xFF for all addresses, which is out of range for a single one.
+                state.write_u8(address.unwrap_or(0xff));
+                state.write_u16(data);
+                state.write_u8(count);
+            }
+            State::Done => state.write_u8(4),
+        }
+        state.write_bool(self.write_enabled);
+        state.write_bool(self.chip_select);
+        state.write_bool(self.clock);
+        state.write_bool(self.data_in);
+        state.write_bool(self.data_out);
+    }
+
+    // Decodes an EEPROM written by `save_state`, for the mapper to swap in once the rest of its
+    // state has been read.
+    pub fn read_state(state: &mut StateReader) -> Result<Eeprom, EmuError> {
+        let data = state.read_bytes(SIZE)?;
+        let eeprom_state = match state.read_u8()? {
+            0 => State::Idle,
+            1 => State::Command {
+                bits: state.read_u16()?,
+                count: state.read_u8()?,
+            },
+            2 => State::Reading {
+                address: state.read_u8()?,
+                data: state.read_u16()?,
+                remaining: state.read_u8()?,
+            },
+            3 => State::Writing {
+                address: match state.read_u8()? {
+                    0xff => None,
+                    address => Some(address),
+                },
+                data: state.read_u16()?,
+                count: state.read_u8()?,
+            },
+            4 => State::Done,
+            _ => {
+                return Err(EmuError::InvalidState {
+                    reason: "unknown EEPROM state",
+                })
+            }
+        };
+        Ok(Eeprom {
+            data,
+            state: eeprom_state,
+            write_enabled: state.read_bool()?,
+            chip_select: state.read_bool()?,
+            clock: state.read_bool()?,
+            data_in: state.read_bool()?,
+            data_out: state.read_bool()?,
+            dirty: false,
+        })
+    }
+
+    // Bit 7: CS, bit 6: CLK, bit 1: DI, bit 0: DO.
+    pub fn read(&self) -> u8 {
+        (self.chip_select as u8) << 7
+            | (self.clock as u8) << 6
+            | (self.data_in as u8) << 1
+            | self.data_out as u8
+    }
+
+    pub fn write(&mut self, value: u8) {
+        let chip_select = value & 0x80 != 0;
+        let clock = value & 0x40 != 0;
+        self.data_in = value & 0x02 != 0;
+
+        if !chip_select {
+            self.state = State::Idle;
+        } else if clock && !self.clock {
+            self.clock_in();
+        }
+        self.chip_select = chip_select;
+        self.clock = clock;
+    }
+
+    // Handles a rising edge of CLK while the chip is selected.
+    fn clock_in(&mut self) {
+        let bit = self.data_in as u16;
+        self.state = match self.state {
+            State::Idle if bit == 1 => State::Command { bits: 0, count: 0 },
+            State::Idle => State::Idle,
+            State::Command { bits, count } => {
+                let bits = bits << 1 | bit;
+                if count + 1 == COMMAND_BITS {
+                    self.execute(bits)
+                } else {
+                    State::Command {
+                        bits,
+                        count: count + 1,
+                    }
+                }
+            }
+            State::Reading {
+                address,
+                data,
+                remaining,
+            } => {
+                self.data_out = data & 0x8000 != 0;
+                if remaining > 1 {
+                    State::Reading {
+                        address,
+                        data: data << 1,
+                        remaining: remaining - 1,
+                    }
+                } else {
+                    let address = (address + 1) % WORDS as u8;
+                    State::Reading {
+                        address,
+                        data: self.word(address),
+                        remaining: 16,
+                    }
+                }
+            }
+            State::Writing {
+                address,
+                data,
+                count,
+            } => {
+                let data = data << 1 | bit;
+                if count + 1 < 16 {
+                    State::Writing {
+                        address,
+                        data,
+                        count: count + 1,
+                    }
+                } else {
+                    match address {
+                        Some(address) => self.program(address, data),
+                        None => {
+                            for address in 0..WORDS as u8 {
+                                self.program(address, data);
+                            }
+                        }
+                    }
+                    // Programming is instant, so DO reports ready straight away.
+                    self.data_out = true;
+                    State::Done
+                }
+            }
+            State::Done => State::Done,
+        };
+    }
+
+    fn execute(&mut self, command: u16) -> State {
+        let address = (command & 0x7f) as u8;
+        match command >> 8 {
+            // READ, preceded by a dummy 0 bit.
+            0b10 => {
+                self.data_out = false;
+                State::Reading {
+                    address,
+                    data: self.word(address),
+                    remaining: 16,
+                }
+            }
+            // WRITE
+            0b01 => State::Writing {
+                address: Some(address),
+                data: 0,
+                count: 0,
+            },
+            // ERASE
+            0b11 => {
+                self.program(address, 0xffff);
+                self.data_out = true;
+                State::Done
+            }
+            _ => match (command >> 6) & 0b11 {
+                // EWDS
+                0b00 => {
+                    self.write_enabled = false;
+                    State::Done
+                }
+                // WRAL
+                0b01 => State::Writing {
+                    address: None,
+                    data: 0,
+                    count: 0,
+                },
+                // ERAL
+                0b10 => {
+                    for address in 0..WORDS as u8 {
+                        self.program(address, 0xffff);
+                    }
+                    self.data_out = true;
+                    State::Done
+                }
+                // EWEN
+                _ => {
+                    self.write_enabled = true;
+                    State::Done
+                }
+            },
+        }
+    }
+
+    fn word(&self, address: u8) -> u16 {
+        let offset = address as usize * 2;
+        u16::from_le_bytes([self.data[offset], self.data[offset + 1]])
+    }
+
+    // Writes and erases are only carried out after EWEN.
+    fn program(&mut self, address: u8, value: u16) {
+        if !self.write_enabled {
+            return;
+        }
+        let offset = address as usize * 2;
+        for (i, byte) in value.to_le_bytes().into_iter().enumerate() {
+            self.dirty |= super::update_ram(&mut self.data, offset + i, byte);
+        }
+    }
+}
+
+impl Default for Eeprom {
+    // Erased, as shipped.
+    fn default() -> Self {
+        Self {
+            data: vec![0xff; SIZE],
+            state: State::Idle,
+            write_enabled: false,
+            chip_select: false,
+            clock: false,
+            data_in: false,
+            data_out: true,
+            dirty: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EWDS: u16 = 0b00_0000_0000;
+    const WRAL: u16 = 0b00_0100_0000;
+    const ERAL: u16 = 0b00_1000_0000;
+    const EWEN: u16 = 0b00_1100_0000;
+
+    fn read_command(address: u8) -> u16 {
+        0b10 << 8 | address as u16
+    }
+
+    fn write_command(address: u8) -> u16 {
+        0b01 << 8 | address as u16
+    }
+
+    fn erase_command(address: u8) -> u16 {
+        0b11 << 8 | address as u16
+    }
+
+    // Clocks bits in on DI, most significant first, returning what DO showed after each rising
+    // edge.
+    fn clock_bits(eeprom: &mut Eeprom, bits: u32, count: u8) -> u32 {
+        let mut out = 0;
+        for i in (0..count).rev() {
+            let data_in = ((bits >> i) & 1) as u8;
+            eeprom.write(0x80 | data_in << 1);
+            eeprom.write(0xc0 | data_in << 1);
+            out = out << 1 | (eeprom.read() & 0x01) as u32;
+        }
+        out
+    }
+
+    // Selects the chip and sends the start bit and a command. CS is left high.
+    fn send(eeprom: &mut Eeprom, command: u16) {
+        eeprom.write(0x00);
+        clock_bits(eeprom, 1 << COMMAND_BITS | command as u32, COMMAND_BITS + 1);
+    }
+
+    fn write_word(eeprom: &mut Eeprom, address: u8, value: u16) {
+        send(eeprom, write_command(address));
+        clock_bits(eeprom, value as u32, 16);
+    }
+
+    fn read_words(eeprom: &mut Eeprom, address: u8, count: u8) -> u32 {
+        send(eeprom, read_command(address));
+        clock_bits(eeprom, 0, 16 * count)
+    }
+
+    #[test]
+    fn write_then_read_back() {
+        let mut eeprom = Eeprom::default();
+        send(&mut eeprom, EWEN);
+        write_word(&mut eeprom, 0x05, 0x1234);
+        // Ready.
+        assert_eq!(eeprom.read() & 0x01, 0x01);
+        assert_eq!(eeprom.data()[0x0a..0x0c], [0x34, 0x12]);
+        assert!(eeprom.take_dirty());
+        assert!(!eeprom.take_dirty());
+
+        send(&mut eeprom, read_command(0x05));
+        // The dummy 0 bit before the data.
+        assert_eq!(eeprom.read() & 0x01, 0x00);
+        assert_eq!(clock_bits(&mut eeprom, 0, 16), 0x1234);
+    }
+
+    #[test]
+    fn reads_continue_into_the_next_word() {
+        let mut eeprom = Eeprom::default();
+        send(&mut eeprom, EWEN);
+        write_word(&mut eeprom, 0x7f, 0xabcd);
+        write_word(&mut eeprom, 0x00, 0x0102);
+        assert_eq!(read_words(&mut eeprom, 0x7f, 2), 0xabcd_0102);
+    }
+
+    #[test]
+    fn writes_are_ignored_until_ewen_and_after_ewds() {
+        let mut eeprom = Eeprom::default();
+        write_word(&mut eeprom, 0x00, 0x0000);
+        assert_eq!(read_words(&mut eeprom, 0x00, 1), 0xffff);
+
+        send(&mut eeprom, EWEN);
+        write_word(&mut eeprom, 0x00, 0x1111);
+        send(&mut eeprom, EWDS);
+        write_word(&mut eeprom, 0x00, 0x2222);
+        send(&mut eeprom, erase_command(0x00));
+        send(&mut eeprom, ERAL);
+        assert_eq!(read_words(&mut eeprom, 0x00, 1), 0x1111);
+        assert!(eeprom.take_dirty());
+        assert!(!eeprom.take_dirty());
+    }
+
+    #[test]
+    fn erase_and_write_all() {
+        let mut eeprom = Eeprom::default();
+        send(&mut eeprom, EWEN);
+        send(&mut eeprom, WRAL);
+        clock_bits(&mut eeprom, 0x5aa5, 16);
+        assert!(eeprom.data().chunks(2).all(|word| word == [0xa5, 0x5a]));
+
+        send(&mut eeprom, erase_command(0x10));
+        assert_eq!(read_words(&mut eeprom, 0x0f, 2), 0x5aa5_ffff);
+
+        send(&mut eeprom, ERAL);
+        assert!(eeprom.data().iter().all(|&byte| byte == 0xff));
+    }
+
+    #[test]
+    fn dropping_cs_aborts_a_command() {
+        let mut eeprom = Eeprom::default();
+        send(&mut eeprom, EWEN);
+        send(&mut eeprom, write_command(0x00));
+        clock_bits(&mut eeprom, 0x12, 8);
+        eeprom.write(0x00);
+        clock_bits(&mut eeprom, 0x34, 8);
+        assert_eq!(read_words(&mut eeprom, 0x00, 1), 0xffff);
+    }
+}
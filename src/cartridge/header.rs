@@ -0,0 +1,336 @@
+use crate::error::EmuError;
+
+// The memory bank controller, or other mapper chip, a cartridge type code stands for.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mapper {
+    None,
+    MBC1,
+    MBC2,
+    MMM01,
+    MBC3,
+    MBC5,
+    MBC6,
+    MBC7,
+    PocketCamera,
+    TAMA5,
+    HuC3,
+    HuC1,
+    // Unlicensed and multicart mappers without a type code of their own. Their headers claim
+    // something else, so `detect::detect_mapper` recognizes them from the ROM contents.
+    M161,
+    WisdomTree,
+    SachenMMC1,
+    SachenMMC2,
+}
+
+// The logo at 0x0104-0x0133 that the boot ROM checks before starting a cartridge.
+pub const NINTENDO_LOGO: [u8; 48] = [
+    0xce, 0xed, 0x66, 0x66, 0xcc, 0x0d, 0x00, 0x0b, 0x03, 0x73, 0x00, 0x83, 0x00, 0x0c, 0x00, 0x0d,
+    0x00, 0x08, 0x11, 0x1f, 0x88, 0x89, 0x00, 0x0e, 0xdc, 0xcc, 0x6e, 0xe6, 0xdd, 0xdd, 0xd9, 0x99,
+    0xbb, 0xbb, 0x67, 0x63, 0x6e, 0x0e, 0xec, 0xcc, 0xdd, 0xdc, 0x99, 0x9f, 0xbb, 0xb9, 0x33, 0x3e,
+];
+
+// Decoded cartridge type byte at 0x0147.
+#[derive(Clone, Copy)]
+pub struct CartridgeType {
+    pub code: u8,
+    pub mapper: Mapper,
+    pub ram: bool,
+    pub battery: bool,
+    pub timer: bool,
+    pub rumble: bool,
+    pub sensor: bool,
+}
+
+impl CartridgeType {
+    pub fn from_code(code: u8) -> Option<CartridgeType> {
+        // (mapper, ram, battery, timer, rumble, sensor)
+        let (mapper, ram, battery, timer, rumble, sensor) = match code {
+            0x00 => (Mapper::None, false, false, false, false, false),
+            0x01 => (Mapper::MBC1, false, false, false, false, false),
+            0x02 => (Mapper::MBC1, true, false, false, false, false),
+            0x03 => (Mapper::MBC1, true, true, false, false, false),
+            0x05 => (Mapper::MBC2, false, false, false, false, false),
+            0x06 => (Mapper::MBC2, false, true, false, false, false),
+            0x08 => (Mapper::None, true, false, false, false, false),
+            0x09 => (Mapper::None, true, true, false, false, false),
+            0x0b => (Mapper::MMM01, false, false, false, false, false),
+            0x0c => (Mapper::MMM01, true, false, false, false, false),
+            0x0d => (Mapper::MMM01, true, true, false, false, false),
+            0x0f => (Mapper::MBC3, false, true, true, false, false),
+            0x10 => (Mapper::MBC3, true, true, true, false, false),
+            0x11 => (Mapper::MBC3, false, false, false, false, false),
+            0x12 => (Mapper::MBC3, true, false, false, false, false),
+            0x13 => (Mapper::MBC3, true, true, false, false, false),
+            0x19 => (Mapper::MBC5, false, false, false, false, false),
+            0x1a => (Mapper::MBC5, true, false, false, false, false),
+            0x1b => (Mapper::MBC5, true, true, false, false, false),
+            0x1c => (Mapper::MBC5, false, false, false, true, false),
+            0x1d => (Mapper::MBC5, true, false, false, true, false),
+            0x1e => (Mapper::MBC5, true, true, false, true, false),
+            0x20 => (Mapper::MBC6, true, true, false, false, false),
+            0x22 => (Mapper::MBC7, true, true, false, true, true),
+            0xfc => (Mapper::PocketCamera, true, true, false, false, true),
+            0xfd => (Mapper::TAMA5, true, true, true, false, false),
+            0xfe => (Mapper::HuC3, true, true, true, false, false),
+            0xff => (Mapper::HuC1, true, true, false, false, false),
+            _ => return None,
+        };
+        Some(CartridgeType {
+            code,
+            mapper,
+            ram,
+            battery,
+            timer,
+            rumble,
+            sensor,
+        })
+    }
+}
+
+// What the CGB flag at 0x0143 says about Game Boy Color support.
+#[derive(Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    None,
+    // Works on DMG too, but uses CGB features when present.
+    Enhanced,
+    Only,
+}
+
+// The cartridge header at 0x0100-0x014F.
+pub struct CartridgeHeader {
+    pub title: String,
+    pub manufacturer_code: Option<String>,
+    pub cgb_support: CgbSupport,
+    pub sgb_support: bool,
+    pub new_licensee_code: [u8; 2],
+    pub old_licensee_code: u8,
+    pub cartridge_type: CartridgeType,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+impl CartridgeHeader {
+    // Parses the header and checks that the ROM is as large as the header says.
+    pub fn parse(rom: &[u8]) -> Result<CartridgeHeader, EmuError> {
+        let header = CartridgeHeader::parse_lenient(rom)?;
+        if rom.len() != header.rom_size {
+            return Err(EmuError::RomSizeMismatch {
+                header_size: header.rom_size,
+                file_size: rom.len(),
+            });
+        }
+        Ok(header)
+    }
+
+    // Parses the header without comparing the declared ROM size to the file, for multicarts and
+    // unlicensed cartridges whose headers don't describe the whole ROM.
+    pub fn parse_lenient(rom: &[u8]) -> Result<CartridgeHeader, EmuError> {
+        if rom.len() < 0x150 {
+            return Err(EmuError::TruncatedRom { size: rom.len() });
+        }
+
+        let cgb_support = match rom[0x143] {
+            0x80 => CgbSupport::Enhanced,
+            0xc0 => CgbSupport::Only,
+            _ => CgbSupport::None,
+        };
+        // Newer cartridges shortened the title to make room for the manufacturer code and the CGB
+        // flag. Older ones use all 16 bytes for the title.
+        let manufacturer_code = &rom[0x13f..=0x142];
+        let (title, manufacturer_code) = if cgb_support == CgbSupport::None {
+            (&rom[0x134..=0x143], None)
+        } else if manufacturer_code
+            .iter()
+            .all(|byte| byte.is_ascii_uppercase() || byte.is_ascii_digit())
+        {
+            (
+                &rom[0x134..=0x13e],
+                Some(String::from_utf8_lossy(manufacturer_code).into_owned()),
+            )
+        } else {
+            (&rom[0x134..=0x142], None)
+        };
+        let title_end = title
+            .iter()
+            .position(|byte| *byte == 0)
+            .unwrap_or(title.len());
+        let title = String::from_utf8_lossy(&title[..title_end]).into_owned();
+
+        let cartridge_type =
+            CartridgeType::from_code(rom[0x147]).ok_or(EmuError::UnknownCartridgeType {
+                cartridge_type: rom[0x147],
+            })?;
+        let rom_size = match rom[0x148] {
+            code @ 0x00..=0x08 => 0x8000 << code,
+            0x52 => 0x120000,
+            0x53 => 0x140000,
+            0x54 => 0x180000,
+            code => return Err(EmuError::UnknownRomSize { code }),
+        };
+        let ram_size = match rom[0x149] {
+            0x00 => 0,
+            0x01 => 0x800,
+            0x02 => 0x2000,
+            0x03 => 0x8000,
+            0x04 => 0x20000,
+            0x05 => 0x10000,
+            code => return Err(EmuError::UnknownRamSize { code }),
+        };
+
+        Ok(CartridgeHeader {
+            title,
+            manufacturer_code,
+            cgb_support,
+            sgb_support: rom[0x146] == 0x03,
+            new_licensee_code: [rom[0x144], rom[0x145]],
+            old_licensee_code: rom[0x14b],
+            cartridge_type,
+            rom_size,
+            ram_size,
+            version: rom[0x14c],
+            header_checksum: rom[0x14d],
+            global_checksum: (rom[0x14e] as u16) << 8 | rom[0x14f] as u16,
+        })
+    }
+
+    // The boot ROM refuses to start a cartridge whose header checksum does not match.
+    pub fn header_checksum_matches(&self, rom: &[u8]) -> bool {
+        let checksum = rom[0x134..=0x14c]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+        checksum == self.header_checksum
+    }
+
+    // Nothing checks the global checksum, so it is often wrong in homebrew and hacks.
+    pub fn global_checksum_matches(&self, rom: &[u8]) -> bool {
+        let checksum = rom
+            .iter()
+            .enumerate()
+            .filter(|(i, _)| *i != 0x14e && *i != 0x14f)
+            .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+        checksum == self.global_checksum
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cartridge::from_rom;
+
+    // A 32 KiB ROM-only cartridge with a correct header checksum.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x134..0x139].copy_from_slice(b"TETRI");
+        set_header_checksum(&mut rom);
+        rom
+    }
+
+    fn set_header_checksum(rom: &mut [u8]) {
+        rom[0x14d] = rom[0x134..=0x14c]
+            .iter()
+            .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    }
+
+    #[test]
+    fn short_file_is_truncated() {
+        let error = CartridgeHeader::parse(&rom()[..0x14f]).err();
+        assert!(matches!(
+            error,
+            Some(EmuError::TruncatedRom { size: 0x14f })
+        ));
+    }
+
+    #[test]
+    fn file_size_must_match_the_header() {
+        let mut rom = rom();
+        rom[0x148] = 0x01;
+        let error = CartridgeHeader::parse(&rom).err();
+        assert!(matches!(
+            error,
+            Some(EmuError::RomSizeMismatch {
+                header_size: 0x10000,
+                file_size: 0x8000
+            })
+        ));
+        assert!(CartridgeHeader::parse_lenient(&rom).is_ok());
+    }
+
+    // The error from parsing a header with one byte changed.
+    fn parse_error(address: usize, value: u8) -> Option<EmuError> {
+        let mut rom = rom();
+        rom[address] = value;
+        CartridgeHeader::parse(&rom).err()
+    }
+
+    #[test]
+    fn unknown_codes_are_rejected() {
+        assert!(matches!(
+            parse_error(0x147, 0x04),
+            Some(EmuError::UnknownCartridgeType {
+                cartridge_type: 0x04
+            })
+        ));
+        assert!(matches!(
+            parse_error(0x148, 0x09),
+            Some(EmuError::UnknownRomSize { code: 0x09 })
+        ));
+        assert!(matches!(
+            parse_error(0x149, 0x06),
+            Some(EmuError::UnknownRamSize { code: 0x06 })
+        ));
+    }
+
+    #[test]
+    fn known_but_unemulated_mapper_is_unsupported() {
+        let mut rom = rom();
+        rom[0x147] = 0x20;
+        let error = from_rom(&rom).err();
+        assert!(matches!(
+            error,
+            Some(EmuError::UnsupportedCartridgeType {
+                cartridge_type: 0x20
+            })
+        ));
+    }
+
+    #[test]
+    fn title_makes_room_for_the_manufacturer_code_on_cgb() {
+        let mut rom = rom();
+        rom[0x134..0x144].copy_from_slice(b"POKEMON CRYSBYTE");
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON CRYSBYTE");
+        assert!(header.manufacturer_code.is_none());
+
+        rom[0x13f..0x144].copy_from_slice(b"BYTE\x80");
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON CRY");
+        assert_eq!(header.manufacturer_code.as_deref(), Some("BYTE"));
+        assert!(header.cgb_support == CgbSupport::Enhanced);
+
+        // Not a manufacturer code, so the title runs up to the CGB flag.
+        rom[0x13f..0x144].copy_from_slice(b"STAL\xc0");
+        rom[0x13f..0x143].make_ascii_lowercase();
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert_eq!(header.title, "POKEMON CRYstal");
+        assert!(header.manufacturer_code.is_none());
+        assert!(header.cgb_support == CgbSupport::Only);
+    }
+
+    #[test]
+    fn header_checksum_covers_0x0134_to_0x014c() {
+        let mut rom = rom();
+        let header = CartridgeHeader::parse(&rom).unwrap();
+        assert!(header.header_checksum_matches(&rom));
+
+        rom[0x14c] = 0x01;
+        assert!(!header.header_checksum_matches(&rom));
+        // Outside the checked range.
+        rom[0x14c] = 0x00;
+        rom[0x150] = 0xff;
+        assert!(header.header_checksum_matches(&rom));
+    }
+}
@@ -0,0 +1,126 @@
+use std::rc::Rc;
+
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+use crate::infrared::{Disconnected, Infrared};
+
+// Value written to 0x0000-0x1FFF that maps the IR port over external RAM.
+const INFRARED_MODE: u8 = 0x0e;
+
+// Hudson HuC1: up to 1 MiB of ROM, 32 KiB of RAM and an infrared port.
+//
+// 0x0000-0x1FFF  0x0E maps the IR port at 0xA000-0xBFFF, anything else maps RAM
+// 0x2000-0x3FFF  ROM bank, 6 bits
+// 0x4000-0x5FFF  RAM bank, 2 bits
+//
+// In IR mode, bit 0 of a write drives the LED and reads return 0xC1 while light is received
+// and 0xC0 otherwise.
+pub struct HuC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    infrared: Rc<dyn Infrared>,
+    infrared_mode: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl HuC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> HuC1 {
+        HuC1 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            infrared: Rc::new(Disconnected),
+            infrared_mode: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Cartridge for HuC1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.infrared_mode = value & 0x0f == INFRARED_MODE,
+            0x2000..=0x3fff => {
+                self.rom_bank = value & 0x3f;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5fff => self.ram_bank = value & 0x03,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.infrared_mode {
+            0xc0 | self.infrared.receiving() as u8
+        } else if self.ram.is_empty() {
+            0xff
+        } else {
+            self.ram[ram_offset(&self.ram, self.ram_bank as usize, address)]
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.infrared_mode {
+            self.infrared.set_emitting(value & 0x01 != 0);
+        } else if !self.ram.is_empty() {
+            let offset = ram_offset(&self.ram, self.ram_bank as usize, address);
+            self.save_dirty |= update_ram(&mut self.ram, offset, value);
+        }
+    }
+
+    fn set_infrared(&mut self, infrared: Rc<dyn Infrared>) {
+        self.infrared = infrared;
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.infrared_mode);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let infrared_mode = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let ram_bank = state.read_u8()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.infrared_mode = infrared_mode;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        self.ram = ram;
+        Ok(())
+    }
+}
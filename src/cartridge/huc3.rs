@@ -0,0 +1,330 @@
+use std::rc::Rc;
+
+use super::rtc::{unix_time, RtcMode, CYCLES_PER_SECOND};
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+use crate::infrared::{Disconnected, Infrared};
+
+const MINUTES_PER_DAY: u16 = 1440;
+// Both time counters are 12 bits wide.
+const DAYS_MASK: u16 = 0xfff;
+
+// Save file footer after RAM, laid out the way SameBoy stores it, all little-endian:
+// 0x00  u64  UNIX timestamp of when the save was written
+// 0x08  u16  minutes of the day
+// 0x0A  u16  days
+// 0x0C  u16  alarm minutes, written as 0 as the alarm isn't emulated
+// 0x0E  u16  alarm days, same
+// 0x10  u8   alarm enabled, same
+const FOOTER_SIZE: usize = 17;
+
+// Nibble addresses in the RTC's memory used by the extended commands.
+const TIME_ADDRESS: usize = 0x00;
+const TONE_ADDRESS: usize = 0x26;
+
+// Hudson HuC3: up to 2 MiB of ROM, 128 KiB of RAM, a real-time clock, an infrared port and a
+// piezo speaker.
+//
+// 0x0000-0x1FFF  selects what 0xA000-0xBFFF maps, from the lower nibble:
+//                0x0 RAM, read only
+//                0xA RAM
+//                0xB RTC command, written
+//                0xC RTC response, read
+//                0xD RTC semaphore: writing bit 0 clear runs the command, reads 1 when ready
+//                0xE IR port, same as HuC1
+// 0x2000-0x3FFF  ROM bank, 7 bits
+// 0x4000-0x5FFF  RAM bank, 4 bits
+//
+// RTC commands are a 3-bit command in bits 4-6 and a 4-bit argument in bits 0-3, working on 256
+// nibbles of RTC memory through an access address:
+// 0x1  read the nibble at the access address, then increment it
+// 0x3  write the argument to the access address, then increment it
+// 0x4  set the lower nibble of the access address
+// 0x5  set the upper nibble of the access address
+// 0x6  extended command selected by the argument:
+//      0x0 copy the clock to memory 0x00-0x05, minutes of the day then days, low nibbles first
+//      0x1 set the clock from memory 0x00-0x05
+//      0x2 status, responds with 1
+//      0xE start the tone if enabled by bit 0 of memory 0x26, stop it otherwise
+pub struct HuC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    infrared: Rc<dyn Infrared>,
+    mode: u8,
+    rom_bank: u8,
+    ram_bank: u8,
+
+    memory: Vec<u8>,
+    access_address: u8,
+    command: u8,
+    response: u8,
+    tone: bool,
+
+    minutes: u16,
+    days: u16,
+    // T-cycles into the current minute.
+    cycles: u64,
+    rtc_mode: RtcMode,
+}
+
+impl HuC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> HuC3 {
+        HuC3 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            infrared: Rc::new(Disconnected),
+            mode: 0,
+            rom_bank: 1,
+            ram_bank: 0,
+
+            memory: vec![0; 0x100],
+            access_address: 0,
+            command: 0,
+            response: 0,
+            tone: false,
+
+            minutes: 0,
+            days: 0,
+            cycles: 0,
+            rtc_mode: RtcMode::WallClock,
+        }
+    }
+
+    // The clock footer that follows RAM in save files.
+    fn clock_footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        footer.extend_from_slice(&unix_time().to_le_bytes());
+        footer.extend_from_slice(&self.minutes.to_le_bytes());
+        footer.extend_from_slice(&self.days.to_le_bytes());
+        footer.resize(FOOTER_SIZE, 0);
+        footer
+    }
+
+    // Restores the clock and advances it by the time since the save was written. Saves without
+    // a footer leave the clock alone.
+    fn load_clock_footer(&mut self, footer: &[u8]) {
+        if footer.len() != FOOTER_SIZE {
+            return;
+        }
+        let saved_at = u64::from_le_bytes(footer[0..8].try_into().unwrap());
+        self.minutes = u16::from_le_bytes([footer[8], footer[9]]) % MINUTES_PER_DAY;
+        self.days = u16::from_le_bytes([footer[10], footer[11]]) & DAYS_MASK;
+        self.cycles = 0;
+        self.catch_up(unix_time().saturating_sub(saved_at));
+    }
+
+    // Accounts for host time that passed while the emulator wasn't running. Ignored in
+    // deterministic mode.
+    fn catch_up(&mut self, seconds: u64) {
+        if self.rtc_mode == RtcMode::Deterministic {
+            return;
+        }
+        self.cycles += seconds % 60 * CYCLES_PER_SECOND as u64;
+        self.advance_minutes(seconds / 60);
+        self.tick(0);
+    }
+
+    fn execute(&mut self) {
+        let argument = self.command & 0x0f;
+        match self.command >> 4 {
+            0x1 => {
+                self.response = self.memory[self.access_address as usize];
+                self.access_address = self.access_address.wrapping_add(1);
+            }
+            0x3 => {
+                self.memory[self.access_address as usize] = argument;
+                self.access_address = self.access_address.wrapping_add(1);
+            }
+            0x4 => self.access_address = (self.access_address & 0xf0) | argument,
+            0x5 => self.access_address = (self.access_address & 0x0f) | argument << 4,
+            0x6 => match argument {
+                0x0 => {
+                    let time = self.minutes as u32 | (self.days as u32) << 12;
+                    for i in 0..6 {
+                        self.memory[TIME_ADDRESS + i] = (time >> (i * 4)) as u8 & 0x0f;
+                    }
+                }
+                0x1 => {
+                    let time = (0..6).fold(0u32, |time, i| {
+                        time | (self.memory[TIME_ADDRESS + i] as u32) << (i * 4)
+                    });
+                    self.minutes = (time & 0xfff) as u16 % MINUTES_PER_DAY;
+                    self.days = (time >> 12) as u16 & DAYS_MASK;
+                    self.save_dirty = true;
+                    self.cycles = 0;
+                }
+                0x2 => self.response = 0x1,
+                0xe => self.tone = self.memory[TONE_ADDRESS] & 0x01 != 0,
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+
+    fn advance_minutes(&mut self, minutes: u64) {
+        let total = self.minutes as u64 + minutes;
+        self.minutes = (total % MINUTES_PER_DAY as u64) as u16;
+        let days = self.days as u64 + total / MINUTES_PER_DAY as u64;
+        self.days = (days & DAYS_MASK as u64) as u16;
+    }
+}
+
+impl Cartridge for HuC3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.mode = value & 0x0f,
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            0x4000..=0x5fff => self.ram_bank = value & 0x0f,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        match self.mode {
+            0x0 | 0xa if !self.ram.is_empty() => {
+                self.ram[ram_offset(&self.ram, self.ram_bank as usize, address)]
+            }
+            0xc => 0x80 | (self.command & 0x70) | self.response,
+            0xd => 0xff,
+            0xe => 0xc0 | self.infrared.receiving() as u8,
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        match self.mode {
+            0xa if !self.ram.is_empty() => {
+                let offset = ram_offset(&self.ram, self.ram_bank as usize, address);
+                self.save_dirty |= update_ram(&mut self.ram, offset, value);
+            }
+            0xb => self.command = value & 0x7f,
+            0xd if value & 0x01 == 0 => self.execute(),
+            0xe => self.infrared.set_emitting(value & 0x01 != 0),
+            _ => {}
+        }
+    }
+
+    // Advances the clock by emulated T-cycles at the base clock rate.
+    fn tick(&mut self, cycles: u32) {
+        self.cycles += cycles as u64;
+        let cycles_per_minute = CYCLES_PER_SECOND as u64 * 60;
+        while self.cycles >= cycles_per_minute {
+            self.cycles -= cycles_per_minute;
+            self.advance_minutes(1);
+        }
+    }
+
+    fn set_rtc_mode(&mut self, mode: RtcMode) {
+        self.rtc_mode = mode;
+    }
+
+    fn set_infrared(&mut self, infrared: Rc<dyn Infrared>) {
+        self.infrared = infrared;
+    }
+
+    // Whether the speaker is playing its tone.
+    fn tone(&self) -> bool {
+        self.tone
+    }
+
+    // RAM followed by the clock footer.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        let mut data = self.ram.clone();
+        data.extend(self.clock_footer());
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let (ram, footer) = data.split_at(self.ram.len().min(data.len()));
+        load_ram(&mut self.ram, ram);
+        self.load_clock_footer(footer);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_u8(self.mode);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bytes(&self.ram);
+        state.write_bytes(&self.memory);
+        state.write_u8(self.access_address);
+        state.write_u8(self.command);
+        state.write_u8(self.response);
+        state.write_bool(self.tone);
+        state.write_u16(self.minutes);
+        state.write_u16(self.days);
+        state.write_u64(self.cycles);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let mode = state.read_u8()?;
+        let rom_bank = state.read_u8()?;
+        let ram_bank = state.read_u8()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        let memory = state.read_bytes(self.memory.len())?;
+        let access_address = state.read_u8()?;
+        let command = state.read_u8()?;
+        let response = state.read_u8()?;
+        let tone = state.read_bool()?;
+        let minutes = state.read_u16()?;
+        let days = state.read_u16()?;
+        let cycles = state.read_u64()?;
+        state.finish()?;
+
+        self.mode = mode;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        self.ram = ram;
+        self.memory = memory;
+        self.access_address = access_address;
+        self.command = command;
+        self.response = response;
+        self.tone = tone;
+        self.minutes = minutes;
+        self.days = days;
+        self.cycles = cycles;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clock_footer_round_trips() {
+        let mut huc3 = HuC3::new(vec![0; 0x8000], 0x2000, true);
+        huc3.set_rtc_mode(RtcMode::Deterministic);
+        huc3.minutes = 754;
+        huc3.days = 321;
+        let data = huc3.save_data().unwrap();
+        assert_eq!(data.len(), 0x2000 + FOOTER_SIZE);
+
+        let mut loaded = HuC3::new(vec![0; 0x8000], 0x2000, true);
+        loaded.set_rtc_mode(RtcMode::Deterministic);
+        loaded.load_save_data(&data);
+        assert_eq!((loaded.minutes, loaded.days), (754, 321));
+    }
+}
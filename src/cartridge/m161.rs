@@ -0,0 +1,62 @@
+use super::state::{StateReader, StateWriter};
+use super::Cartridge;
+use crate::error::EmuError;
+
+// M161: the mapper of the Mani 4 in 1 multicarts. It switches the whole ROM area between 32 KiB
+// games, and only once: the first bank select latches until the next power cycle, so a game
+// can't get back to the menu.
+//
+// 0x4000-0x5FFF  32 KiB ROM bank, 3 bits, written once
+pub struct M161 {
+    rom: Vec<u8>,
+    rom_bank: u8,
+    locked: bool,
+}
+
+impl M161 {
+    pub fn new(rom: Vec<u8>) -> M161 {
+        M161 {
+            rom,
+            rom_bank: 0,
+            locked: false,
+        }
+    }
+}
+
+impl Cartridge for M161 {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom[(self.rom_bank as usize * 0x8000 + address as usize) % self.rom.len()]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if (0x4000..=0x5fff).contains(&address) && !self.locked {
+            self.rom_bank = value & 0x07;
+            self.locked = true;
+        }
+    }
+
+    // There is no external RAM.
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xff
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_u8(self.rom_bank);
+        state.write_bool(self.locked);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let rom_bank = state.read_u8()?;
+        let locked = state.read_bool()?;
+        state.finish()?;
+
+        self.rom_bank = rom_bank;
+        self.locked = locked;
+        Ok(())
+    }
+}
@@ -0,0 +1,258 @@
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// Bank number of the second game in an MBC1M multicart, where a copy of the Nintendo logo sits.
+const MULTICART_GAME_BANK: usize = 0x10;
+const LOGO_START: usize = 0x104;
+const LOGO_END: usize = 0x134;
+
+// MBC1: up to 2 MiB of ROM and 32 KiB of RAM.
+//
+// 0x0000-0x1FFF  RAM enable, 0xA in the lower nibble enables
+// 0x2000-0x3FFF  lower ROM bank register, 5 bits (4 wired on MBC1M)
+// 0x4000-0x5FFF  secondary register, 2 bits: upper ROM bank bits or RAM bank
+// 0x6000-0x7FFF  banking mode select
+pub struct MBC1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    ram_enabled: bool,
+    rom_bank: u8,
+    secondary_bank: u8,
+    // In mode 1 the secondary register also banks 0x0000-0x3FFF and external RAM.
+    advanced_banking: bool,
+    // MBC1M multicarts leave bit 4 of the lower register unconnected, so the secondary register
+    // starts at bit 4 of the ROM bank instead of bit 5.
+    multicart: bool,
+}
+
+impl MBC1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> MBC1 {
+        let multicart = is_multicart(&rom);
+        MBC1 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            ram_enabled: false,
+            rom_bank: 1,
+            secondary_bank: 0,
+            advanced_banking: false,
+            multicart,
+        }
+    }
+
+    fn lower_bank_bits(&self) -> usize {
+        if self.multicart {
+            (self.rom_bank & 0x0f) as usize
+        } else {
+            self.rom_bank as usize
+        }
+    }
+
+    fn upper_bank_bits(&self) -> usize {
+        if self.multicart {
+            (self.secondary_bank as usize) << 4
+        } else {
+            (self.secondary_bank as usize) << 5
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            self.secondary_bank as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Cartridge for MBC1 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff if self.advanced_banking => self.upper_bank_bits(),
+            0x0000..=0x3fff => 0,
+            _ => self.upper_bank_bits() | self.lower_bank_bits(),
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => {
+                // Bank 0 can't be selected here. The check looks at all five bits, so banks 0x20,
+                // 0x40 and 0x60 are unreachable in the upper half.
+                self.rom_bank = value & 0x1f;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5fff => self.secondary_bank = value & 0x03,
+            _ => self.advanced_banking = value & 0x01 != 0,
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xff;
+        }
+        self.ram[ram_offset(&self.ram, self.ram_bank(), address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let offset = ram_offset(&self.ram, self.ram_bank(), address);
+        self.save_dirty |= update_ram(&mut self.ram, offset, value);
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.secondary_bank);
+        state.write_bool(self.advanced_banking);
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_enabled = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let secondary_bank = state.read_u8()?;
+        let advanced_banking = state.read_bool()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.ram_enabled = ram_enabled;
+        self.rom_bank = rom_bank;
+        self.secondary_bank = secondary_bank;
+        self.advanced_banking = advanced_banking;
+        self.ram = ram;
+        Ok(())
+    }
+}
+
+// MBC1M carts are 1 MiB compilations whose games each start with their own header. The header
+// says plain MBC1, so look for the Nintendo logo at the start of the second game instead.
+fn is_multicart(rom: &[u8]) -> bool {
+    if rom.len() != 0x100000 {
+        return false;
+    }
+    let game_start = MULTICART_GAME_BANK * 0x4000;
+    rom[LOGO_START..LOGO_END] == rom[game_start + LOGO_START..game_start + LOGO_END]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A 1 MiB ROM whose banks start with their own number, with a logo in the first header only.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x100000];
+        for bank in 0..0x40 {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        for (i, byte) in rom[LOGO_START..LOGO_END].iter_mut().enumerate() {
+            *byte = i as u8 + 1;
+        }
+        rom
+    }
+
+    fn multicart_rom() -> Vec<u8> {
+        let mut rom = rom();
+        let game_start = MULTICART_GAME_BANK * 0x4000;
+        rom.copy_within(LOGO_START..LOGO_END, game_start + LOGO_START);
+        rom
+    }
+
+    #[test]
+    fn bank_0_selects_bank_1() {
+        let mut mbc1 = MBC1::new(rom(), 0, false);
+        assert_eq!(mbc1.read_rom(0x4000), 0x01);
+        mbc1.write_rom(0x2000, 0x05);
+        assert_eq!(mbc1.read_rom(0x4000), 0x05);
+        mbc1.write_rom(0x2000, 0x00);
+        assert_eq!(mbc1.read_rom(0x4000), 0x01);
+
+        // 0x20 is bank 0 in the lower register, so bank 0x21 is mapped instead.
+        mbc1.write_rom(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x4000), 0x21);
+    }
+
+    #[test]
+    fn mode_1_banks_the_lower_half_with_the_secondary_register() {
+        let mut mbc1 = MBC1::new(rom(), 0, false);
+        mbc1.write_rom(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x0000), 0x00);
+
+        mbc1.write_rom(0x6000, 0x01);
+        assert_eq!(mbc1.read_rom(0x0000), 0x20);
+        assert_eq!(mbc1.read_rom(0x4000), 0x21);
+
+        mbc1.write_rom(0x6000, 0x00);
+        assert_eq!(mbc1.read_rom(0x0000), 0x00);
+        assert_eq!(mbc1.read_rom(0x4000), 0x21);
+    }
+
+    #[test]
+    fn mode_1_banks_ram_with_the_secondary_register() {
+        let mut mbc1 = MBC1::new(rom(), 0x8000, true);
+        mbc1.write_rom(0x0000, 0x0a);
+        mbc1.write_rom(0x4000, 0x02);
+        mbc1.write_ram(0xa000, 0x12);
+        assert_eq!(mbc1.save_data().unwrap()[0], 0x12);
+
+        mbc1.write_rom(0x6000, 0x01);
+        mbc1.write_ram(0xa000, 0x34);
+        assert_eq!(mbc1.save_data().unwrap()[0x4000], 0x34);
+        assert_eq!(mbc1.read_ram(0xa000), 0x34);
+
+        mbc1.write_rom(0x0000, 0x00);
+        assert_eq!(mbc1.read_ram(0xa000), 0xff);
+    }
+
+    #[test]
+    fn multicart_is_detected_by_the_second_logo() {
+        assert!(!MBC1::new(rom(), 0, false).multicart);
+        assert!(MBC1::new(multicart_rom(), 0, false).multicart);
+
+        let mut rom = multicart_rom();
+        rom.truncate(0x80000);
+        assert!(!MBC1::new(rom, 0, false).multicart);
+    }
+
+    #[test]
+    fn multicart_banks_shift_the_secondary_register_by_4() {
+        let mut mbc1 = MBC1::new(multicart_rom(), 0, false);
+        mbc1.write_rom(0x2000, 0x12);
+        assert_eq!(mbc1.read_rom(0x4000), 0x02);
+        mbc1.write_rom(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x4000), 0x12);
+        mbc1.write_rom(0x4000, 0x03);
+        assert_eq!(mbc1.read_rom(0x4000), 0x32);
+
+        // Mode 1 maps the first bank of each game into the lower half.
+        mbc1.write_rom(0x6000, 0x01);
+        assert_eq!(mbc1.read_rom(0x0000), 0x30);
+        mbc1.write_rom(0x4000, 0x01);
+        assert_eq!(mbc1.read_rom(0x0000), 0x10);
+    }
+}
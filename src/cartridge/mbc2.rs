@@ -0,0 +1,156 @@
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// Half-bytes of RAM built into the MBC2 chip.
+const RAM_SIZE: usize = 0x200;
+
+// MBC2: up to 256 KiB of ROM in 16 banks and 512x4 bits of built-in RAM.
+//
+// Both registers live at 0x0000-0x3FFF and bit 8 of the address picks between them:
+// clear  RAM enable, 0xA in the lower nibble enables
+// set    ROM bank, 4 bits
+pub struct MBC2 {
+    rom: Vec<u8>,
+    // Only the lower nibble of each byte is stored.
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    ram_enabled: bool,
+    rom_bank: u8,
+}
+
+impl MBC2 {
+    pub fn new(rom: Vec<u8>, battery: bool) -> MBC2 {
+        MBC2 {
+            rom,
+            ram: vec![0; RAM_SIZE],
+            battery,
+            save_dirty: false,
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Cartridge for MBC2 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        if address >= 0x4000 {
+            return;
+        }
+        if address & 0x0100 == 0 {
+            self.ram_enabled = value & 0x0f == 0x0a;
+        } else {
+            self.rom_bank = value & 0x0f;
+            if self.rom_bank == 0 {
+                self.rom_bank = 1;
+            }
+        }
+    }
+
+    // The 512 half-bytes repeat across the whole of 0xA000-0xBFFF. The upper nibble isn't
+    // connected and reads as 1s.
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+        self.ram[address as usize % RAM_SIZE] | 0xf0
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_enabled {
+            let offset = address as usize % RAM_SIZE;
+            self.save_dirty |= update_ram(&mut self.ram, offset, value & 0x0f);
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+        // Other emulators may have stored the unconnected upper nibble as 1s.
+        for value in self.ram.iter_mut() {
+            *value &= 0x0f;
+        }
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_enabled = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.ram_enabled = ram_enabled;
+        self.rom_bank = rom_bank;
+        self.ram = ram;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x40000];
+        for bank in 0..0x10 {
+            rom[bank * 0x4000] = bank as u8;
+        }
+        rom
+    }
+
+    #[test]
+    fn address_bit_8_picks_the_register() {
+        let mut mbc2 = MBC2::new(rom(), false);
+        mbc2.write_rom(0x0100, 0x0a);
+        assert_eq!(mbc2.read_ram(0xa000), 0xff);
+        assert_eq!(mbc2.read_rom(0x4000), 0x0a);
+
+        mbc2.write_rom(0x3e00, 0x0a);
+        assert_eq!(mbc2.read_ram(0xa000), 0xf0);
+        mbc2.write_rom(0x2100, 0x1f);
+        assert_eq!(mbc2.read_rom(0x4000), 0x0f);
+        mbc2.write_rom(0x2100, 0x10);
+        assert_eq!(mbc2.read_rom(0x4000), 0x01);
+    }
+
+    #[test]
+    fn ram_is_512_nibbles_mirrored_with_the_upper_nibble_set() {
+        let mut mbc2 = MBC2::new(rom(), true);
+        mbc2.write_rom(0x0000, 0x0a);
+        mbc2.write_ram(0xa001, 0xa5);
+        assert_eq!(mbc2.read_ram(0xa001), 0xf5);
+        assert_eq!(mbc2.read_ram(0xa201), 0xf5);
+        assert_eq!(mbc2.read_ram(0xbe01), 0xf5);
+
+        mbc2.write_ram(0xbfff, 0x03);
+        assert_eq!(mbc2.read_ram(0xa1ff), 0xf3);
+
+        let save = mbc2.save_data().unwrap();
+        assert_eq!(save.len(), RAM_SIZE);
+        assert_eq!((save[0x001], save[0x1ff]), (0x05, 0x03));
+    }
+}
@@ -0,0 +1,162 @@
+use super::rtc::{Rtc, RtcMode};
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// MBC3: up to 2 MiB of ROM, 32 KiB of RAM and an optional real-time clock.
+//
+// 0x0000-0x1FFF  RAM and RTC enable, 0xA in the lower nibble enables
+// 0x2000-0x3FFF  ROM bank, 7 bits
+// 0x4000-0x5FFF  RAM bank 0x00-0x07 or RTC register 0x08-0x0C
+// 0x6000-0x7FFF  RTC latch
+pub struct MBC3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    rtc: Option<Rtc>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+}
+
+impl MBC3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool, has_rtc: bool) -> MBC3 {
+        MBC3 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            rtc: if has_rtc { Some(Rtc::default()) } else { None },
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+        }
+    }
+}
+
+impl Cartridge for MBC3 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => {
+                self.rom_bank = value & 0x7f;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5fff => self.ram_bank = value & 0x0f,
+            _ => {
+                if let Some(rtc) = &mut self.rtc {
+                    rtc.write_latch(value);
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled {
+            return 0xff;
+        }
+        match (self.ram_bank, &self.rtc) {
+            (0x00..=0x07, _) if !self.ram.is_empty() => {
+                self.ram[ram_offset(&self.ram, self.ram_bank as usize, address)]
+            }
+            (0x08..=0x0c, Some(rtc)) => rtc.read(self.ram_bank),
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled {
+            return;
+        }
+        match (self.ram_bank, &mut self.rtc) {
+            (0x00..=0x07, _) if !self.ram.is_empty() => {
+                let offset = ram_offset(&self.ram, self.ram_bank as usize, address);
+                self.save_dirty |= update_ram(&mut self.ram, offset, value);
+            }
+            (0x08..=0x0c, Some(rtc)) => {
+                rtc.write(self.ram_bank, value);
+                self.save_dirty = true;
+            }
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick(cycles);
+        }
+    }
+
+    fn set_rtc_mode(&mut self, mode: RtcMode) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.set_mode(mode);
+        }
+    }
+
+    // RAM followed by the RTC footer, if there is a clock.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        if !self.battery {
+            return None;
+        }
+        let mut data = self.ram.clone();
+        if let Some(rtc) = &self.rtc {
+            data.extend(rtc.footer());
+        }
+        Some(data)
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let (ram, footer) = data.split_at(self.ram.len().min(data.len()));
+        load_ram(&mut self.ram, ram);
+        if let Some(rtc) = &mut self.rtc {
+            rtc.load_footer(footer);
+        }
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_enabled);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bytes(&self.ram);
+        if let Some(rtc) = &self.rtc {
+            rtc.save_state(&mut state);
+        }
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_enabled = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let ram_bank = state.read_u8()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        let rtc = match &self.rtc {
+            Some(rtc) => Some(rtc.read_state(&mut state)?),
+            None => None,
+        };
+        state.finish()?;
+
+        self.ram_enabled = ram_enabled;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        self.ram = ram;
+        self.rtc = rtc;
+        Ok(())
+    }
+}
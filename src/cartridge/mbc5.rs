@@ -0,0 +1,195 @@
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// MBC5: up to 8 MiB of ROM and 128 KiB of RAM.
+//
+// 0x0000-0x1FFF  RAM enable, exactly 0x0A enables
+// 0x2000-0x2FFF  lower 8 bits of the ROM bank
+// 0x3000-0x3FFF  bit 8 of the ROM bank
+// 0x4000-0x5FFF  RAM bank, 4 bits. On rumble carts bit 3 drives the motor instead.
+pub struct MBC5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    has_rumble: bool,
+    ram_enabled: bool,
+    // Unlike earlier mappers, bank 0 can be mapped at 0x4000-0x7FFF too.
+    rom_bank: u16,
+    ram_bank: u8,
+    rumble: bool,
+}
+
+impl MBC5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool, has_rumble: bool) -> MBC5 {
+        MBC5 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            has_rumble,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            rumble: false,
+        }
+    }
+}
+
+impl Cartridge for MBC5 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_enabled = value == 0x0a,
+            0x2000..=0x2fff => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3fff => {
+                self.rom_bank = (self.rom_bank & 0xff) | ((value & 0x01) as u16) << 8
+            }
+            0x4000..=0x5fff => {
+                if self.has_rumble {
+                    self.ram_bank = value & 0x07;
+                    self.rumble = value & 0x08 != 0;
+                } else {
+                    self.ram_bank = value & 0x0f;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xff;
+        }
+        self.ram[ram_offset(&self.ram, self.ram_bank as usize, address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let offset = ram_offset(&self.ram, self.ram_bank as usize, address);
+        self.save_dirty |= update_ram(&mut self.ram, offset, value);
+    }
+
+    // Whether the rumble motor is currently running.
+    fn rumble(&self) -> bool {
+        self.rumble
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_enabled);
+        state.write_u16(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bool(self.rumble);
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_enabled = state.read_bool()?;
+        let rom_bank = state.read_u16()?;
+        let ram_bank = state.read_u8()?;
+        let rumble = state.read_bool()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.ram_enabled = ram_enabled;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        self.rumble = rumble;
+        self.ram = ram;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cpu::memory_bus::MemoryBus;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    // 8 MiB, the most MBC5 can address, with each bank starting with its own number.
+    fn rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x800000];
+        for bank in 0..0x200 {
+            rom[bank * 0x4000..bank * 0x4000 + 2].copy_from_slice(&(bank as u16).to_le_bytes());
+        }
+        rom
+    }
+
+    fn mapped_bank(mbc5: &MBC5) -> u16 {
+        u16::from_le_bytes([mbc5.read_rom(0x4000), mbc5.read_rom(0x4001)])
+    }
+
+    #[test]
+    fn rom_bank_has_9_bits_and_can_be_0() {
+        let mut mbc5 = MBC5::new(rom(), 0, false, false);
+        assert_eq!(mapped_bank(&mbc5), 0x001);
+        mbc5.write_rom(0x2000, 0x00);
+        assert_eq!(mapped_bank(&mbc5), 0x000);
+        mbc5.write_rom(0x3000, 0xff);
+        assert_eq!(mapped_bank(&mbc5), 0x100);
+        mbc5.write_rom(0x2000, 0x42);
+        assert_eq!(mapped_bank(&mbc5), 0x142);
+        mbc5.write_rom(0x3000, 0x00);
+        assert_eq!(mapped_bank(&mbc5), 0x042);
+        assert_eq!(mbc5.read_rom(0x0000), 0x00);
+    }
+
+    #[test]
+    fn rumble_takes_bit_3_of_the_ram_bank() {
+        let mut mbc5 = MBC5::new(rom(), 0x20000, true, true);
+        mbc5.write_rom(0x0000, 0x0a);
+        mbc5.write_rom(0x4000, 0x09);
+        assert!(mbc5.rumble());
+        mbc5.write_ram(0xa000, 0x12);
+        assert_eq!(mbc5.save_data().unwrap()[0x2000], 0x12);
+
+        // Without a motor, bit 3 is part of the bank number.
+        let mut mbc5 = MBC5::new(rom(), 0x20000, true, false);
+        mbc5.write_rom(0x0000, 0x0a);
+        mbc5.write_rom(0x4000, 0x09);
+        assert!(!mbc5.rumble());
+        mbc5.write_ram(0xa000, 0x12);
+        assert_eq!(mbc5.save_data().unwrap()[0x12000], 0x12);
+    }
+
+    #[test]
+    fn rumble_changes_reach_the_callback() {
+        let mut bus = MemoryBus::default();
+        bus.load_cartridge(Box::new(MBC5::new(rom(), 0, false, true)), None);
+        let reported = Rc::new(RefCell::new(Vec::new()));
+        let callback_reported = reported.clone();
+        bus.set_rumble_callback(Box::new(move |on| callback_reported.borrow_mut().push(on)));
+
+        bus.write_byte(0x4000, 0x08);
+        bus.write_byte(0x4000, 0x0b);
+        bus.write_byte(0x4000, 0x03);
+        bus.write_byte(0x4000, 0x00);
+        assert_eq!(*reported.borrow(), [true, false]);
+    }
+}
@@ -0,0 +1,246 @@
+use super::eeprom::Eeprom;
+use super::state::{StateReader, StateWriter};
+use super::{rom_offset, Cartridge};
+use crate::error::EmuError;
+
+// Accelerometer reading when level, and how far it moves per 1 g of tilt.
+const ACCELEROMETER_CENTER: f32 = 0x81d0 as f32;
+const ACCELEROMETER_PER_G: f32 = 0x70 as f32;
+// Latched value after an erase, until the next latch.
+const ACCELEROMETER_ERASED: u16 = 0x8000;
+
+// MBC7: up to 2 MiB of ROM, a two-axis accelerometer and a 93LC56 serial EEPROM.
+//
+// 0x0000-0x1FFF  first RAM enable, 0x0A enables
+// 0x2000-0x3FFF  ROM bank, 7 bits
+// 0x4000-0x5FFF  second RAM enable, 0x40 enables
+//
+// With both enables set, bits 4-7 of the address select a register in 0xA000-0xAFFF:
+// 0xA00x  write 0x55 to erase the latched accelerometer values
+// 0xA01x  write 0xAA to latch the accelerometer
+// 0xA02x  X, low byte
+// 0xA03x  X, high byte
+// 0xA04x  Y, low byte
+// 0xA05x  Y, high byte
+// 0xA06x  always 0x00
+// 0xA08x  EEPROM pins
+pub struct MBC7 {
+    rom: Vec<u8>,
+    eeprom: Eeprom,
+    ram_enabled: bool,
+    ram_enabled_2: bool,
+    rom_bank: u8,
+    // Tilt in g fed by the host, positive to the right and towards the bottom.
+    tilt_x: f32,
+    tilt_y: f32,
+    latched_x: u16,
+    latched_y: u16,
+    // A new latch only happens after the previous values have been erased.
+    latch_erased: bool,
+}
+
+impl MBC7 {
+    pub fn new(rom: Vec<u8>) -> MBC7 {
+        MBC7 {
+            rom,
+            eeprom: Eeprom::default(),
+            ram_enabled: false,
+            ram_enabled_2: false,
+            rom_bank: 1,
+            tilt_x: 0.0,
+            tilt_y: 0.0,
+            latched_x: ACCELEROMETER_ERASED,
+            latched_y: ACCELEROMETER_ERASED,
+            latch_erased: false,
+        }
+    }
+
+    // The registers only appear in the lower half of 0xA000-0xBFFF.
+    fn registers_enabled(&self, address: u16) -> bool {
+        self.ram_enabled && self.ram_enabled_2 && address < 0xb000
+    }
+}
+
+impl Cartridge for MBC7 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_enabled = value == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = value & 0x7f,
+            0x4000..=0x5fff => self.ram_enabled_2 = value == 0x40,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.registers_enabled(address) {
+            return 0xff;
+        }
+        match address & 0x00f0 {
+            0x20 => self.latched_x as u8,
+            0x30 => (self.latched_x >> 8) as u8,
+            0x40 => self.latched_y as u8,
+            0x50 => (self.latched_y >> 8) as u8,
+            0x60 => 0x00,
+            0x80 => self.eeprom.read(),
+            _ => 0xff,
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.registers_enabled(address) {
+            return;
+        }
+        match address & 0x00f0 {
+            0x00 if value == 0x55 => {
+                self.latched_x = ACCELEROMETER_ERASED;
+                self.latched_y = ACCELEROMETER_ERASED;
+                self.latch_erased = true;
+            }
+            0x10 if value == 0xaa && self.latch_erased => {
+                self.latched_x = accelerometer_value(self.tilt_x);
+                self.latched_y = accelerometer_value(self.tilt_y);
+                self.latch_erased = false;
+            }
+            0x80 => self.eeprom.write(value),
+            _ => {}
+        }
+    }
+
+    // Sets the tilt the accelerometer reports at the next latch, in g on each axis.
+    fn set_tilt(&mut self, x: f32, y: f32) {
+        self.tilt_x = x;
+        self.tilt_y = y;
+    }
+
+    // The EEPROM stands in for battery RAM in save files.
+    fn save_data(&self) -> Option<Vec<u8>> {
+        Some(self.eeprom.data().to_vec())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        self.eeprom.load(data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        self.eeprom.take_dirty()
+    }
+
+    // The host's tilt is left alone.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_enabled);
+        state.write_bool(self.ram_enabled_2);
+        state.write_u8(self.rom_bank);
+        state.write_u16(self.latched_x);
+        state.write_u16(self.latched_y);
+        state.write_bool(self.latch_erased);
+        self.eeprom.save_state(&mut state);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_enabled = state.read_bool()?;
+        let ram_enabled_2 = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let latched_x = state.read_u16()?;
+        let latched_y = state.read_u16()?;
+        let latch_erased = state.read_bool()?;
+        let eeprom = Eeprom::read_state(&mut state)?;
+        state.finish()?;
+
+        self.ram_enabled = ram_enabled;
+        self.ram_enabled_2 = ram_enabled_2;
+        self.rom_bank = rom_bank;
+        self.latched_x = latched_x;
+        self.latched_y = latched_y;
+        self.latch_erased = latch_erased;
+        self.eeprom = eeprom;
+        Ok(())
+    }
+}
+
+fn accelerometer_value(tilt: f32) -> u16 {
+    (ACCELEROMETER_CENTER + tilt * ACCELEROMETER_PER_G).clamp(0.0, u16::MAX as f32) as u16
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn enabled_mbc7() -> MBC7 {
+        let mut mbc7 = MBC7::new(vec![0; 0x8000]);
+        mbc7.write_rom(0x0000, 0x0a);
+        mbc7.write_rom(0x4000, 0x40);
+        mbc7
+    }
+
+    fn latched(mbc7: &MBC7) -> (u16, u16) {
+        let x = u16::from_le_bytes([mbc7.read_ram(0xa020), mbc7.read_ram(0xa030)]);
+        let y = u16::from_le_bytes([mbc7.read_ram(0xa040), mbc7.read_ram(0xa050)]);
+        (x, y)
+    }
+
+    #[test]
+    fn registers_need_both_enables() {
+        let mut mbc7 = MBC7::new(vec![0; 0x8000]);
+        mbc7.write_rom(0x0000, 0x0a);
+        assert_eq!(mbc7.read_ram(0xa060), 0xff);
+        mbc7.write_rom(0x4000, 0x40);
+        assert_eq!(mbc7.read_ram(0xa060), 0x00);
+        assert_eq!(mbc7.read_ram(0xb060), 0xff);
+    }
+
+    #[test]
+    fn accelerometer_latches_only_after_an_erase() {
+        let mut mbc7 = enabled_mbc7();
+        mbc7.set_tilt(1.0, -0.5);
+        mbc7.write_ram(0xa010, 0xaa);
+        assert_eq!(latched(&mbc7), (0x8000, 0x8000));
+
+        mbc7.write_ram(0xa000, 0x55);
+        mbc7.write_ram(0xa010, 0xaa);
+        assert_eq!(latched(&mbc7), (0x8240, 0x8198));
+
+        // Without another erase, the values stay put.
+        mbc7.set_tilt(0.0, 0.0);
+        mbc7.write_ram(0xa010, 0xaa);
+        assert_eq!(latched(&mbc7), (0x8240, 0x8198));
+
+        mbc7.write_ram(0xa000, 0x55);
+        assert_eq!(latched(&mbc7), (0x8000, 0x8000));
+        mbc7.write_ram(0xa010, 0xaa);
+        assert_eq!(latched(&mbc7), (0x81d0, 0x81d0));
+    }
+
+    fn clock_bits(mbc7: &mut MBC7, bits: u32, count: u8) {
+        for i in (0..count).rev() {
+            let data_in = ((bits >> i) & 1) as u8;
+            mbc7.write_ram(0xa080, 0x80 | data_in << 1);
+            mbc7.write_ram(0xa080, 0xc0 | data_in << 1);
+        }
+    }
+
+    #[test]
+    fn eeprom_is_driven_through_the_pin_register() {
+        let mut mbc7 = enabled_mbc7();
+        // EWEN, then WRITE 0xbeef to word 1.
+        mbc7.write_ram(0xa080, 0x00);
+        clock_bits(&mut mbc7, 0b100_1100_0000, 11);
+        mbc7.write_ram(0xa080, 0x00);
+        clock_bits(&mut mbc7, 0b101_0000_0001, 11);
+        clock_bits(&mut mbc7, 0xbeef, 16);
+        assert_eq!(mbc7.read_ram(0xa080) & 0x01, 0x01);
+
+        assert_eq!(mbc7.save_data().unwrap()[2..4], [0xef, 0xbe]);
+        assert!(mbc7.take_save_dirty());
+    }
+}
@@ -0,0 +1,197 @@
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// MMM01: an MBC1-like multicart controller. It powers up unmapped, with the last 32 KiB of ROM,
+// where the menu lives, in both halves of the ROM area. The menu sets up the outer bank bits and
+// masks for the chosen game, then maps it in, which freezes them until the next power cycle.
+//
+// 0x0000-0x1FFF  RAM enable in the lower nibble. While unmapped, also the RAM bank mask
+//                (bits 4-5) and map enable (bit 6)
+// 0x2000-0x3FFF  ROM bank bits 0-4, as on MBC1. While unmapped, also ROM bank bits 5-6
+// 0x4000-0x5FFF  RAM bank bits 0-1. While unmapped, also RAM bank bits 2-3 (bits 2-3), ROM bank
+//                bits 7-8 (bits 4-5) and a lock on the banking mode (bit 6)
+// 0x6000-0x7FFF  banking mode select. While unmapped, also the ROM bank mask (bits 2-5)
+pub struct MMM01 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    mapped: bool,
+    ram_enabled: bool,
+    rom_bank: u16,
+    // Bits 1-4 of the ROM bank, one per bit, that the game can no longer change once mapped.
+    rom_bank_mask: u8,
+    ram_bank: u8,
+    // Same, for bits 0-1 of the RAM bank.
+    ram_bank_mask: u8,
+    advanced_banking: bool,
+    banking_mode_locked: bool,
+}
+
+impl MMM01 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> MMM01 {
+        MMM01 {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            mapped: false,
+            ram_enabled: false,
+            rom_bank: 1,
+            rom_bank_mask: 0,
+            ram_bank: 0,
+            ram_bank_mask: 0,
+            advanced_banking: false,
+            banking_mode_locked: false,
+        }
+    }
+
+    // ROM bank bits 0-4 that the game still controls.
+    fn writable_rom_bank_bits(&self) -> u16 {
+        if self.mapped {
+            0x1f & !((self.rom_bank_mask as u16) << 1)
+        } else {
+            0x1f
+        }
+    }
+
+    // RAM bank bits 0-1 that the game still controls.
+    fn writable_ram_bank_bits(&self) -> u8 {
+        if self.mapped {
+            0x03 & !self.ram_bank_mask
+        } else {
+            0x03
+        }
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.advanced_banking {
+            self.ram_bank as usize
+        } else {
+            (self.ram_bank & !self.writable_ram_bank_bits()) as usize
+        }
+    }
+}
+
+impl Cartridge for MMM01 {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            // The upper bank bits are all forced high, which is the end of the ROM at any size.
+            0x0000..=0x3fff if !self.mapped => 0x1fe,
+            _ if !self.mapped => 0x1ff,
+            // Bank 0 of the game, keeping the bits the menu fixed.
+            0x0000..=0x3fff => (self.rom_bank & !self.writable_rom_bank_bits()) as usize,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => {
+                self.ram_enabled = value & 0x0f == 0x0a;
+                if !self.mapped {
+                    self.ram_bank_mask = (value >> 4) & 0x03;
+                    self.mapped = value & 0x40 != 0;
+                }
+            }
+            0x2000..=0x3fff => {
+                let writable = self.writable_rom_bank_bits();
+                let mut low = (self.rom_bank & !writable) | (value as u16 & writable);
+                // Like MBC1, bank 0 of the game can't be selected here. Only the bits the game
+                // controls are checked, and bit 0 is never fixed.
+                if low & writable == 0 {
+                    low |= 1;
+                }
+                self.rom_bank = (self.rom_bank & !0x1f) | (low & 0x1f);
+                if !self.mapped {
+                    self.rom_bank = (self.rom_bank & !0x60) | (value as u16 & 0x60);
+                }
+            }
+            0x4000..=0x5fff => {
+                let writable = self.writable_ram_bank_bits();
+                self.ram_bank = (self.ram_bank & !writable) | (value & writable);
+                if !self.mapped {
+                    self.ram_bank = (self.ram_bank & 0x03) | (value & 0x0c);
+                    self.rom_bank = (self.rom_bank & 0x7f) | ((value as u16 & 0x30) << 3);
+                    self.banking_mode_locked = value & 0x40 != 0;
+                }
+            }
+            _ => {
+                if !self.banking_mode_locked {
+                    self.advanced_banking = value & 0x01 != 0;
+                }
+                if !self.mapped {
+                    self.rom_bank_mask = (value >> 2) & 0x0f;
+                }
+            }
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return 0xff;
+        }
+        self.ram[ram_offset(&self.ram, self.ram_bank(), address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if !self.ram_enabled || self.ram.is_empty() {
+            return;
+        }
+        let offset = ram_offset(&self.ram, self.ram_bank(), address);
+        self.save_dirty |= update_ram(&mut self.ram, offset, value);
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.mapped);
+        state.write_bool(self.ram_enabled);
+        state.write_u16(self.rom_bank);
+        state.write_u8(self.rom_bank_mask);
+        state.write_u8(self.ram_bank);
+        state.write_u8(self.ram_bank_mask);
+        state.write_bool(self.advanced_banking);
+        state.write_bool(self.banking_mode_locked);
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let mapped = state.read_bool()?;
+        let ram_enabled = state.read_bool()?;
+        let rom_bank = state.read_u16()?;
+        let rom_bank_mask = state.read_u8()?;
+        let ram_bank = state.read_u8()?;
+        let ram_bank_mask = state.read_u8()?;
+        let advanced_banking = state.read_bool()?;
+        let banking_mode_locked = state.read_bool()?;
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.mapped = mapped;
+        self.ram_enabled = ram_enabled;
+        self.rom_bank = rom_bank;
+        self.rom_bank_mask = rom_bank_mask;
+        self.ram_bank = ram_bank;
+        self.ram_bank_mask = ram_bank_mask;
+        self.advanced_banking = advanced_banking;
+        self.banking_mode_locked = banking_mode_locked;
+        self.ram = ram;
+        Ok(())
+    }
+}
@@ -0,0 +1,307 @@
+pub mod image;
+
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, ram_offset, rom_offset, update_ram, Cartridge};
+use crate::error::EmuError;
+use image::{ImageSource, SENSOR_HEIGHT, SENSOR_WIDTH};
+
+// RAM bank value with this bit set maps the camera registers instead of RAM.
+const REGISTER_BANK: u8 = 0x10;
+const REGISTER_COUNT: usize = 0x36;
+
+// Registers of the M64282FP sensor and the mapper's image processing.
+const SHOOT: usize = 0x00;
+const EDGE_AND_GAIN: usize = 0x01;
+const EXPOSURE_HIGH: usize = 0x02;
+const EXPOSURE_LOW: usize = 0x03;
+const EDGE_RATIO_AND_INVERT: usize = 0x04;
+const DITHER_MATRIX: usize = 0x06;
+
+// Where a capture ends up in RAM bank 0, as 16x14 tiles in the usual 2bpp tile format.
+const CAPTURE_ADDRESS: usize = 0x0100;
+
+// Edge enhancement ratios selected by bits 4-6 of register 4, in quarters.
+const EDGE_RATIOS: [i32; 8] = [2, 3, 4, 5, 8, 12, 16, 20];
+
+// Game Boy Camera (Pocket Camera): up to 1 MiB of ROM, 128 KiB of RAM and a 128x112 image
+// sensor.
+//
+// 0x0000-0x1FFF  RAM write enable, 0x0A enables. RAM can always be read.
+// 0x2000-0x3FFF  ROM bank, 6 bits
+// 0x4000-0x5FFF  RAM bank 0x00-0x0F, or 0x10 to map the camera registers
+//
+// The camera registers at 0xA000-0xA035 are write only, except bit 0 of 0xA000 which starts a
+// capture and reads 1 until it is done:
+// 0xA000       bit 0: capture
+// 0xA001       bit 7: exclusive edge mode, bits 5-6: edge direction, bits 0-4: gain
+// 0xA002-A003  exposure time, high byte first
+// 0xA004       bits 4-6: edge enhancement ratio, bit 3: invert
+// 0xA005       output offsets
+// 0xA006-A035  4x4 dithering matrix of 3 thresholds per pixel
+pub struct PocketCamera {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+    ram_write_enabled: bool,
+    rom_bank: u8,
+    ram_bank: u8,
+    registers: [u8; REGISTER_COUNT],
+    // T-cycles left until the running capture completes.
+    capture_remaining: Option<u32>,
+    image_source: Box<dyn ImageSource>,
+}
+
+impl PocketCamera {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> PocketCamera {
+        PocketCamera {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+            ram_write_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            registers: [0; REGISTER_COUNT],
+            capture_remaining: None,
+            image_source: Box::new(image::Blank),
+        }
+    }
+
+    fn exposure(&self) -> u32 {
+        (self.registers[EXPOSURE_HIGH] as u32) << 8 | self.registers[EXPOSURE_LOW] as u32
+    }
+
+    // Readout of the sensor takes a fixed time, plus the exposure time in steps of 16 us.
+    fn capture_cycles(&self) -> u32 {
+        let exclusive_edge = self.registers[EDGE_AND_GAIN] & 0x80 != 0;
+        129792 + if exclusive_edge { 0 } else { 2048 } + self.exposure() * 64
+    }
+
+    fn capture(&mut self) {
+        if self.ram.len() < CAPTURE_ADDRESS + image::PHOTO_SIZE {
+            return;
+        }
+        // Frames come from the host, so one of the wrong size is cut off or padded with black.
+        let mut image = self.image_source.capture();
+        image.resize(SENSOR_WIDTH * SENSOR_HEIGHT, 0);
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let color = self.dither(x, y, self.enhanced_color(&image, x, y));
+                let tile = (y / 8) * (SENSOR_WIDTH / 8) + x / 8;
+                let offset = CAPTURE_ADDRESS + tile * 16 + (y % 8) * 2;
+                let bit = 0x80 >> (x % 8);
+                for (plane, mask) in [(0, 0x01), (1, 0x02)] {
+                    if color & mask != 0 {
+                        self.ram[offset + plane] |= bit;
+                    } else {
+                        self.ram[offset + plane] &= !bit;
+                    }
+                }
+            }
+        }
+    }
+
+    // Sensor output for a pixel after gain and exposure, with coordinates clamped to the edges.
+    fn sensed_color(&self, image: &[u8], x: isize, y: isize) -> i32 {
+        let x = x.clamp(0, SENSOR_WIDTH as isize - 1) as usize;
+        let y = y.clamp(0, SENSOR_HEIGHT as isize - 1) as usize;
+        let gain = (self.registers[EDGE_AND_GAIN] & 0x1f) as i64;
+        let color = image[y * SENSOR_WIDTH + x] as i64 * self.exposure() as i64 * (32 + gain)
+            / (0x800 * 32);
+        let color = color.min(0xff) as i32;
+        if self.registers[EDGE_RATIO_AND_INVERT] & 0x08 != 0 {
+            0xff - color
+        } else {
+            color
+        }
+    }
+
+    // Applies edge enhancement in the directions selected by bits 5-6 of register 1.
+    fn enhanced_color(&self, image: &[u8], x: usize, y: usize) -> i32 {
+        let (x, y) = (x as isize, y as isize);
+        let color = self.sensed_color(image, x, y);
+        let ratio = EDGE_RATIOS[(self.registers[EDGE_RATIO_AND_INVERT] >> 4 & 0x07) as usize];
+        let mut edges = 0;
+        let direction = self.registers[EDGE_AND_GAIN] >> 5 & 0x03;
+        if direction & 0x01 != 0 {
+            edges +=
+                2 * color - self.sensed_color(image, x - 1, y) - self.sensed_color(image, x + 1, y);
+        }
+        if direction & 0x02 != 0 {
+            edges +=
+                2 * color - self.sensed_color(image, x, y - 1) - self.sensed_color(image, x, y + 1);
+        }
+        color + edges * ratio / 4
+    }
+
+    // Turns a sensor value into a shade, 0 white to 3 black, with the thresholds of the matrix
+    // entry for this pixel.
+    fn dither(&self, x: usize, y: usize, color: i32) -> u8 {
+        let entry = DITHER_MATRIX + ((y & 3) * 4 + (x & 3)) * 3;
+        let thresholds = &self.registers[entry..entry + 3];
+        if color < thresholds[0] as i32 {
+            3
+        } else if color < thresholds[1] as i32 {
+            2
+        } else if color < thresholds[2] as i32 {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+impl Cartridge for PocketCamera {
+    fn read_rom(&self, address: u16) -> u8 {
+        let bank = match address {
+            0x0000..=0x3fff => 0,
+            _ => self.rom_bank as usize,
+        };
+        self.rom[rom_offset(&self.rom, bank, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff => self.ram_write_enabled = value & 0x0f == 0x0a,
+            0x2000..=0x3fff => self.rom_bank = value & 0x3f,
+            0x4000..=0x5fff => self.ram_bank = value & 0x1f,
+            _ => {}
+        }
+    }
+
+    fn read_ram(&self, address: u16) -> u8 {
+        if self.ram_bank & REGISTER_BANK != 0 {
+            return match address & 0x7f {
+                0x00 => self.registers[SHOOT] & 0x06 | self.capture_remaining.is_some() as u8,
+                _ => 0x00,
+            };
+        }
+        // The sensor has the RAM bus while capturing.
+        if self.capture_remaining.is_some() || self.ram.is_empty() {
+            return 0x00;
+        }
+        self.ram[ram_offset(&self.ram, self.ram_bank as usize, address)]
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        if self.ram_bank & REGISTER_BANK != 0 {
+            let register = (address & 0x7f) as usize;
+            if register == SHOOT {
+                self.registers[SHOOT] = value & 0x07;
+                if value & 0x01 != 0 && self.capture_remaining.is_none() {
+                    self.capture_remaining = Some(self.capture_cycles());
+                } else if value & 0x01 == 0 {
+                    self.capture_remaining = None;
+                }
+            } else if register < REGISTER_COUNT {
+                self.registers[register] = value;
+            }
+            return;
+        }
+        if !self.ram_write_enabled || self.capture_remaining.is_some() || self.ram.is_empty() {
+            return;
+        }
+        let offset = ram_offset(&self.ram, self.ram_bank as usize, address);
+        self.save_dirty |= update_ram(&mut self.ram, offset, value);
+    }
+
+    fn tick(&mut self, cycles: u32) {
+        if let Some(remaining) = self.capture_remaining {
+            if remaining > cycles {
+                self.capture_remaining = Some(remaining - cycles);
+            } else {
+                self.capture();
+                self.save_dirty = true;
+                self.capture_remaining = None;
+                self.registers[SHOOT] &= !0x01;
+            }
+        }
+    }
+
+    fn set_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.image_source = image_source;
+    }
+
+    // Writes each photo saved in the camera's album to `directory` as a PNG and returns how many
+    // there were.
+    fn export_photos(&self, directory: &str) -> Result<usize, EmuError> {
+        image::export_photos(&self.ram, directory)
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    // The image source belongs to the host and is left alone.
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bool(self.ram_write_enabled);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.ram_bank);
+        state.write_bytes(&self.registers);
+        // 0 when no capture is running, as a running one always has cycles left.
+        state.write_u32(self.capture_remaining.unwrap_or(0));
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram_write_enabled = state.read_bool()?;
+        let rom_bank = state.read_u8()?;
+        let ram_bank = state.read_u8()?;
+        let mut registers = [0; REGISTER_COUNT];
+        state.read_bytes_into(&mut registers)?;
+        let capture_remaining = match state.read_u32()? {
+            0 => None,
+            remaining => Some(remaining),
+        };
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.ram_write_enabled = ram_write_enabled;
+        self.rom_bank = rom_bank;
+        self.ram_bank = ram_bank;
+        self.registers = registers;
+        self.capture_remaining = capture_remaining;
+        self.ram = ram;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn capture_pads_a_short_frame() {
+        let mut camera = PocketCamera::new(vec![0; 0x8000], 0x20000, true);
+        camera.set_image_source(Box::new(|| vec![0xff; 10]));
+        camera.write_rom(0x4000, REGISTER_BANK);
+        // Exposure that passes the frame through unchanged, and even dithering thresholds.
+        camera.write_ram(0xa000 + EXPOSURE_HIGH as u16, 0x08);
+        for entry in 0..16 {
+            for (i, threshold) in [0x40, 0x80, 0xc0].into_iter().enumerate() {
+                camera.write_ram(0xa000 + (DITHER_MATRIX + entry * 3 + i) as u16, threshold);
+            }
+        }
+        camera.write_ram(0xa000, 0x01);
+        camera.tick(u32::MAX);
+
+        camera.write_rom(0x4000, 0x00);
+        // The first pixels were white and the padding black.
+        let first_row = CAPTURE_ADDRESS;
+        let last_row = CAPTURE_ADDRESS + image::PHOTO_SIZE - 2;
+        assert_eq!(camera.read_ram(0xa000 + first_row as u16), 0x00);
+        assert_eq!(camera.read_ram(0xa000 + last_row as u16), 0xff);
+    }
+}
@@ -0,0 +1,255 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::error::{read_file, EmuError};
+
+pub const SENSOR_WIDTH: usize = 128;
+pub const SENSOR_HEIGHT: usize = 112;
+// Bytes of tile data in one photo.
+pub const PHOTO_SIZE: usize = SENSOR_WIDTH * SENSOR_HEIGHT / 4;
+
+// Where the camera ROM keeps its album in RAM: one state byte per slot in bank 0, 0xFF for an
+// empty slot, and the photos themselves from bank 1 on in 0x1000 byte slots.
+const ALBUM_SLOTS: usize = 30;
+const ALBUM_STATE_ADDRESS: usize = 0x11b2;
+const ALBUM_PHOTO_ADDRESS: usize = 0x2000;
+const ALBUM_SLOT_SIZE: usize = 0x1000;
+
+// Shades written to exported photos, from white to black.
+const SHADES: [u8; 4] = [0xff, 0xaa, 0x55, 0x00];
+
+// What the camera sensor sees: a 128x112 grayscale frame, row by row from the top left, 0 for
+// black and 255 for white.
+pub trait ImageSource {
+    fn capture(&mut self) -> Vec<u8>;
+}
+
+// Lets the frontend feed frames from a closure, e.g. from a webcam.
+impl<F: FnMut() -> Vec<u8>> ImageSource for F {
+    fn capture(&mut self) -> Vec<u8> {
+        self()
+    }
+}
+
+// Used until the host supplies something to look at: an even mid gray.
+pub struct Blank;
+
+impl ImageSource for Blank {
+    fn capture(&mut self) -> Vec<u8> {
+        vec![0x80; SENSOR_WIDTH * SENSOR_HEIGHT]
+    }
+}
+
+// The same picture on every capture, loaded from a PNG or PGM file and scaled to the sensor.
+pub struct StillImage {
+    pixels: Vec<u8>,
+}
+
+impl StillImage {
+    pub fn from_file(path: &str) -> Result<StillImage, EmuError> {
+        let data = read_file(path)?;
+        let image = if data.starts_with(b"\x89PNG") {
+            decode_png(&data)
+        } else if data.starts_with(b"P2") || data.starts_with(b"P5") {
+            decode_pgm(&data)
+        } else {
+            Err("not a PNG or PGM image".to_string())
+        };
+        let (width, height, pixels) = image.map_err(|reason| EmuError::Image {
+            path: path.to_string(),
+            reason,
+        })?;
+
+        // Nearest neighbour is plenty for a 128x112 sensor.
+        let mut scaled = Vec::with_capacity(SENSOR_WIDTH * SENSOR_HEIGHT);
+        for y in 0..SENSOR_HEIGHT {
+            for x in 0..SENSOR_WIDTH {
+                let source_x = x * width / SENSOR_WIDTH;
+                let source_y = y * height / SENSOR_HEIGHT;
+                scaled.push(pixels[source_y * width + source_x]);
+            }
+        }
+        Ok(StillImage { pixels: scaled })
+    }
+}
+
+impl ImageSource for StillImage {
+    fn capture(&mut self) -> Vec<u8> {
+        self.pixels.clone()
+    }
+}
+
+// Writes each photo in the album as `photo_NN.png`, numbered by slot, and returns how many were
+// written.
+pub fn export_photos(ram: &[u8], directory: &str) -> Result<usize, EmuError> {
+    if ram.len() < ALBUM_PHOTO_ADDRESS + ALBUM_SLOTS * ALBUM_SLOT_SIZE {
+        return Ok(0);
+    }
+    let mut count = 0;
+    for slot in 0..ALBUM_SLOTS {
+        if ram[ALBUM_STATE_ADDRESS + slot] == 0xff {
+            continue;
+        }
+        let start = ALBUM_PHOTO_ADDRESS + slot * ALBUM_SLOT_SIZE;
+        let path = Path::new(directory).join(format!("photo_{:02}.png", slot + 1));
+        write_photo(&ram[start..start + PHOTO_SIZE], &path.to_string_lossy())?;
+        count += 1;
+    }
+    Ok(count)
+}
+
+// Saves 2bpp tile data laid out as 16x14 tiles as a grayscale PNG.
+pub fn write_photo(tiles: &[u8], path: &str) -> Result<(), EmuError> {
+    let mut pixels = vec![0; SENSOR_WIDTH * SENSOR_HEIGHT];
+    for (i, pixel) in pixels.iter_mut().enumerate() {
+        let (x, y) = (i % SENSOR_WIDTH, i / SENSOR_WIDTH);
+        let offset = ((y / 8) * (SENSOR_WIDTH / 8) + x / 8) * 16 + (y % 8) * 2;
+        let bit = 7 - x % 8;
+        let shade = (tiles[offset] >> bit & 0x01) | (tiles[offset + 1] >> bit & 0x01) << 1;
+        *pixel = SHADES[shade as usize];
+    }
+
+    let file = File::create(path).map_err(|error| EmuError::Io {
+        path: path.to_string(),
+        error,
+    })?;
+    let mut encoder = png::Encoder::new(
+        BufWriter::new(file),
+        SENSOR_WIDTH as u32,
+        SENSOR_HEIGHT as u32,
+    );
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&pixels))
+        .map_err(|error| EmuError::Image {
+            path: path.to_string(),
+            reason: error.to_string(),
+        })
+}
+
+// Decodes to 8-bit grayscale, returning width, height and pixels.
+fn decode_png(data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    let mut decoder = png::Decoder::new(data);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info().map_err(|error| error.to_string())?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader
+        .next_frame(&mut buffer)
+        .map_err(|error| error.to_string())?;
+
+    let channels = match info.color_type {
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        png::ColorType::Rgb => 3,
+        png::ColorType::Rgba => 4,
+        png::ColorType::Indexed => return Err("unexpanded palette".to_string()),
+    };
+    let pixels = buffer[..info.buffer_size()]
+        .chunks(info.line_size)
+        .flat_map(|line| line.chunks(channels).take(info.width as usize))
+        .map(|pixel| match channels {
+            1 | 2 => pixel[0],
+            _ => {
+                ((pixel[0] as u32 * 299 + pixel[1] as u32 * 587 + pixel[2] as u32 * 114) / 1000)
+                    as u8
+            }
+        })
+        .collect();
+    Ok((info.width as usize, info.height as usize, pixels))
+}
+
+// Decodes binary (P5) and plain (P2) PGM, returning width, height and pixels scaled to 8 bits.
+fn decode_pgm(data: &[u8]) -> Result<(usize, usize, Vec<u8>), String> {
+    let mut position = 2;
+    let mut header = [0; 3];
+    for value in header.iter_mut() {
+        *value = next_pgm_number(data, &mut position).ok_or("truncated PGM header")?;
+    }
+    let [width, height, max_value] = header;
+    if width == 0 || height == 0 || max_value == 0 || max_value > 0xffff {
+        return Err("invalid PGM header".to_string());
+    }
+
+    // Every sample takes at least a byte, so a count past the file size is a lie, and checking
+    // that first keeps a crafted header from allocating a huge image.
+    let count = width
+        .checked_mul(height)
+        .filter(|&count| count <= data.len())
+        .ok_or("PGM dimensions larger than the file")?;
+    let samples: Vec<usize> = if data.starts_with(b"P5") {
+        // A single whitespace byte separates the header from the samples.
+        let start = position + 1;
+        let bytes_per_sample = if max_value > 0xff { 2 } else { 1 };
+        let end = count
+            .checked_mul(bytes_per_sample)
+            .and_then(|length| length.checked_add(start))
+            .filter(|&end| end <= data.len())
+            .ok_or("truncated PGM data")?;
+        data[start..end]
+            .chunks(bytes_per_sample)
+            .map(|sample| {
+                sample
+                    .iter()
+                    .fold(0, |value, &byte| value << 8 | byte as usize)
+            })
+            .collect()
+    } else {
+        (0..count)
+            .map(|_| next_pgm_number(data, &mut position))
+            .collect::<Option<_>>()
+            .ok_or("truncated PGM data")?
+    };
+    let pixels = samples
+        .iter()
+        .map(|&sample| (sample.min(max_value) * 0xff / max_value) as u8)
+        .collect();
+    Ok((width, height, pixels))
+}
+
+// Reads the next decimal number, skipping whitespace and comments.
+fn next_pgm_number(data: &[u8], position: &mut usize) -> Option<usize> {
+    loop {
+        match data.get(*position)? {
+            b'#' => {
+                while *data.get(*position)? != b'\n' {
+                    *position += 1;
+                }
+            }
+            byte if byte.is_ascii_whitespace() => *position += 1,
+            _ => break,
+        }
+    }
+    let start = *position;
+    while data
+        .get(*position)
+        .is_some_and(|byte| byte.is_ascii_digit())
+    {
+        *position += 1;
+    }
+    std::str::from_utf8(&data[start..*position])
+        .ok()?
+        .parse()
+        .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pgm_with_oversized_dimensions_is_rejected() {
+        let overflowing = format!("P5 {} 2 255\n\0\0", usize::MAX);
+        assert!(decode_pgm(overflowing.as_bytes()).is_err());
+        assert!(decode_pgm(b"P5 100000 100000 255\n\0\0").is_err());
+        assert!(decode_pgm(b"P2 100000 100000 255\n0 0").is_err());
+    }
+
+    #[test]
+    fn pgm_is_scaled_to_8_bits() {
+        let (width, height, pixels) = decode_pgm(b"P2\n# comment\n2 1 15\n0 15\n").unwrap();
+        assert_eq!((width, height, pixels), (2, 1, vec![0x00, 0xff]));
+    }
+}
@@ -0,0 +1,76 @@
+use super::state::{StateReader, StateWriter};
+use super::{load_ram, update_ram, Cartridge};
+use crate::error::EmuError;
+
+// A cartridge without a memory bank controller: 32 KiB of ROM and at most 8 KiB of RAM, both
+// mapped directly.
+pub struct RomOnly {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    battery: bool,
+    save_dirty: bool,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>, ram_size: usize, battery: bool) -> RomOnly {
+        RomOnly {
+            rom,
+            ram: vec![0; ram_size],
+            battery,
+            save_dirty: false,
+        }
+    }
+}
+
+impl Cartridge for RomOnly {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom.get(address as usize).copied().unwrap_or(0xff)
+    }
+
+    // There is nothing to control, so writes are ignored.
+    fn write_rom(&mut self, _address: u16, _value: u8) {}
+
+    // Reads from missing external RAM float high.
+    fn read_ram(&self, address: u16) -> u8 {
+        let offset = (address - 0xa000) as usize;
+        if offset < self.ram.len() {
+            self.ram[offset]
+        } else {
+            0xff
+        }
+    }
+
+    fn write_ram(&mut self, address: u16, value: u8) {
+        let offset = (address - 0xa000) as usize;
+        if offset < self.ram.len() {
+            self.save_dirty |= update_ram(&mut self.ram, offset, value);
+        }
+    }
+
+    fn save_data(&self) -> Option<Vec<u8>> {
+        self.battery.then(|| self.ram.clone())
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        load_ram(&mut self.ram, data);
+    }
+
+    fn take_save_dirty(&mut self) -> bool {
+        std::mem::take(&mut self.save_dirty)
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_bytes(&self.ram);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let ram = state.read_bytes(self.ram.len())?;
+        state.finish()?;
+
+        self.ram = ram;
+        Ok(())
+    }
+}
@@ -0,0 +1,326 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use super::state::{StateReader, StateWriter};
+use crate::error::EmuError;
+
+// T-cycles per second of the base clock. The RTC has its own 32.768 kHz crystal, so it ignores
+// CGB double speed.
+pub const CYCLES_PER_SECOND: u32 = 4_194_304;
+
+// Bits of the upper day counter register.
+const DAY_HIGH_BIT: u8 = 0x01;
+const HALT_BIT: u8 = 0x40;
+const DAY_CARRY_BIT: u8 = 0x80;
+
+// The footer appended to the RAM dump in save files, as used by BGB and VBA-M: the current and
+// latched registers as 32-bit little-endian values, then a 64-bit UNIX timestamp of when the save
+// was written. Older VBA-M saves have a 32-bit timestamp instead.
+pub const FOOTER_SIZE: usize = 48;
+const SHORT_FOOTER_SIZE: usize = 44;
+
+// Which time source drives the cartridge clock.
+#[derive(Clone, Copy, PartialEq)]
+pub enum RtcMode {
+    // Follows emulated time while running, so it speeds up when fast-forwarding and stops while
+    // paused, and catches up with the host clock for the time the emulator was off.
+    WallClock,
+    // Only ever advanced by emulated cycles, so replays are reproducible.
+    Deterministic,
+}
+
+// The MBC3 real-time clock. Register indices are the values written to the RAM bank register:
+//
+// 0x08  seconds, 0-59
+// 0x09  minutes, 0-59
+// 0x0A  hours, 0-23
+// 0x0B  lower 8 bits of the day counter
+// 0x0C  bit 0: bit 8 of the day counter, bit 6: halt, bit 7: day counter carry
+pub struct Rtc {
+    seconds: u8,
+    minutes: u8,
+    hours: u8,
+    days: u16,
+    halted: bool,
+    day_carry: bool,
+    // Snapshot of the registers taken when latching, which is what reads return.
+    latched: [u8; 5],
+    // Latching happens when 0x00 and then 0x01 is written.
+    latch_armed: bool,
+    // T-cycles into the current second.
+    cycles: u32,
+    mode: RtcMode,
+}
+
+impl Rtc {
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    // Writes go to the live counters and show up in the latched copy immediately. Writing the
+    // seconds also restarts the current second.
+    pub fn write(&mut self, register: u8, value: u8) {
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3f;
+                self.cycles = 0;
+            }
+            0x09 => self.minutes = value & 0x3f,
+            0x0a => self.hours = value & 0x1f,
+            0x0b => self.days = (self.days & 0x100) | value as u16,
+            _ => {
+                self.days = (self.days & 0xff) | ((value & DAY_HIGH_BIT) as u16) << 8;
+                self.halted = value & HALT_BIT != 0;
+                self.day_carry = value & DAY_CARRY_BIT != 0;
+            }
+        }
+        self.latched[(register - 0x08) as usize] = self.registers()[(register - 0x08) as usize];
+    }
+
+    pub fn write_latch(&mut self, value: u8) {
+        if self.latch_armed && value == 0x01 {
+            self.latched = self.registers();
+        }
+        self.latch_armed = value == 0x00;
+    }
+
+    pub fn set_mode(&mut self, mode: RtcMode) {
+        self.mode = mode;
+    }
+
+    pub fn footer(&self) -> Vec<u8> {
+        let mut footer = Vec::with_capacity(FOOTER_SIZE);
+        for register in self.registers().iter().chain(self.latched.iter()) {
+            footer.extend_from_slice(&(*register as u32).to_le_bytes());
+        }
+        footer.extend_from_slice(&unix_time().to_le_bytes());
+        footer
+    }
+
+    // Restores the clock from a save file footer and advances it by the time since the save was
+    // written. Footers of the wrong size are ignored.
+    pub fn load_footer(&mut self, footer: &[u8]) {
+        if footer.len() != FOOTER_SIZE && footer.len() != SHORT_FOOTER_SIZE {
+            return;
+        }
+        let value = |index: usize| footer[index * 4];
+
+        self.seconds = value(0) & 0x3f;
+        self.minutes = value(1) & 0x3f;
+        self.hours = value(2) & 0x1f;
+        self.days = value(3) as u16 | ((value(4) & DAY_HIGH_BIT) as u16) << 8;
+        self.halted = value(4) & HALT_BIT != 0;
+        self.day_carry = value(4) & DAY_CARRY_BIT != 0;
+        for (i, latched) in self.latched.iter_mut().enumerate() {
+            *latched = value(5 + i);
+        }
+        self.cycles = 0;
+
+        let mut timestamp = [0; 8];
+        timestamp[..footer.len() - 40].copy_from_slice(&footer[40..]);
+        let saved_at = u64::from_le_bytes(timestamp);
+        self.catch_up(unix_time().saturating_sub(saved_at));
+    }
+
+    // Unlike the footer, keeps the time within the current second and doesn't catch up.
+    pub fn save_state(&self, state: &mut StateWriter) {
+        for register in self.registers().iter().chain(self.latched.iter()) {
+            state.write_u8(*register);
+        }
+        state.write_bool(self.latch_armed);
+        state.write_u32(self.cycles);
+    }
+
+    // Decodes a clock written by `save_state`, leaving this one alone so nothing changes if the
+    // rest of the state turns out to be bad. The mode isn't part of the state and is kept.
+    pub fn read_state(&self, state: &mut StateReader) -> Result<Rtc, EmuError> {
+        let mut rtc = Rtc {
+            mode: self.mode,
+            ..Rtc::default()
+        };
+        let mut registers = [0; 5];
+        for register in registers.iter_mut() {
+            *register = state.read_u8()?;
+        }
+        for (i, register) in registers.iter().enumerate() {
+            rtc.write(0x08 + i as u8, *register);
+        }
+        for latched in rtc.latched.iter_mut() {
+            *latched = state.read_u8()?;
+        }
+        rtc.latch_armed = state.read_bool()?;
+        rtc.cycles = state.read_u32()?;
+        Ok(rtc)
+    }
+
+    // Advances the clock by emulated T-cycles at the base clock rate.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.halted {
+            return;
+        }
+        self.cycles += cycles;
+        while self.cycles >= CYCLES_PER_SECOND {
+            self.cycles -= CYCLES_PER_SECOND;
+            self.advance_second();
+        }
+    }
+
+    // Accounts for host time that passed while the emulator wasn't running. Ignored in
+    // deterministic mode.
+    pub fn catch_up(&mut self, seconds: u64) {
+        if self.mode == RtcMode::Deterministic || self.halted {
+            return;
+        }
+
+        // Out of range values count up to the register's limit without carrying, so step through
+        // them one second at a time until everything is back in range.
+        let mut remaining = seconds;
+        while remaining > 0 && (self.seconds >= 60 || self.minutes >= 60 || self.hours >= 24) {
+            self.advance_second();
+            remaining -= 1;
+        }
+
+        let total = remaining
+            + self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days > 0x1ff {
+            self.day_carry = true;
+        }
+        self.days = (days & 0x1ff) as u16;
+    }
+
+    fn advance_second(&mut self) {
+        self.seconds = (self.seconds + 1) & 0x3f;
+        if self.seconds != 60 {
+            return;
+        }
+        self.seconds = 0;
+
+        self.minutes = (self.minutes + 1) & 0x3f;
+        if self.minutes != 60 {
+            return;
+        }
+        self.minutes = 0;
+
+        self.hours = (self.hours + 1) & 0x1f;
+        if self.hours != 24 {
+            return;
+        }
+        self.hours = 0;
+
+        self.days += 1;
+        if self.days > 0x1ff {
+            self.days = 0;
+            self.day_carry = true;
+        }
+    }
+
+    fn registers(&self) -> [u8; 5] {
+        [
+            self.seconds,
+            self.minutes,
+            self.hours,
+            self.days as u8,
+            (self.days >> 8) as u8 & DAY_HIGH_BIT
+                | if self.halted { HALT_BIT } else { 0 }
+                | if self.day_carry { DAY_CARRY_BIT } else { 0 },
+        ]
+    }
+}
+
+// Seconds since the UNIX epoch on the host clock.
+pub fn unix_time() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs())
+}
+
+impl Default for Rtc {
+    fn default() -> Self {
+        Self {
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halted: false,
+            day_carry: false,
+            latched: [0; 5],
+            latch_armed: false,
+            cycles: 0,
+            mode: RtcMode::WallClock,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn latch(rtc: &mut Rtc) {
+        rtc.write_latch(0x00);
+        rtc.write_latch(0x01);
+    }
+
+    #[test]
+    fn reads_only_change_when_latching_0_then_1() {
+        let mut rtc = Rtc::default();
+        rtc.tick(CYCLES_PER_SECOND);
+        assert_eq!(rtc.read(0x08), 0);
+
+        rtc.write_latch(0x01);
+        assert_eq!(rtc.read(0x08), 0);
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x08), 1);
+    }
+
+    #[test]
+    fn day_counter_overflow_sets_the_carry() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x08, 59);
+        rtc.write(0x09, 59);
+        rtc.write(0x0a, 23);
+        rtc.write(0x0b, 0xff);
+        rtc.write(0x0c, DAY_HIGH_BIT);
+        rtc.tick(CYCLES_PER_SECOND);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.read(0x0b), 0x00);
+        assert_eq!(rtc.read(0x0c), DAY_CARRY_BIT);
+    }
+
+    #[test]
+    fn halt_stops_the_clock() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x0c, HALT_BIT);
+        rtc.tick(5 * CYCLES_PER_SECOND);
+        rtc.catch_up(3600);
+
+        latch(&mut rtc);
+        assert_eq!(rtc.registers(), [0, 0, 0, 0, HALT_BIT]);
+    }
+
+    #[test]
+    fn loading_a_footer_catches_up_with_the_host_clock() {
+        let mut rtc = Rtc::default();
+        rtc.write(0x0a, 5);
+        let mut footer = rtc.footer();
+        // Pretend the save was written a day and an hour ago.
+        let saved_at = unix_time() - 25 * 3600;
+        footer[40..].copy_from_slice(&saved_at.to_le_bytes());
+
+        let mut loaded = Rtc::default();
+        loaded.load_footer(&footer);
+        assert_eq!(loaded.hours, 6);
+        assert_eq!(loaded.days, 1);
+
+        let mut deterministic = Rtc::default();
+        deterministic.set_mode(RtcMode::Deterministic);
+        deterministic.load_footer(&footer);
+        assert_eq!((deterministic.hours, deterministic.days), (5, 0));
+    }
+}
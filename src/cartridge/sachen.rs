@@ -0,0 +1,106 @@
+use super::state::{StateReader, StateWriter};
+use super::{rom_offset, Cartridge};
+use crate::boot_rom::BootRom;
+use crate::error::EmuError;
+
+// Sachen MMC1 and MMC2. The banking is MBC1-like, with an outer base bank that the game can only
+// change while bits 4-5 of the ROM bank are set.
+//
+// 0x0000-0x1FFF  base ROM bank, only while the ROM bank register has bits 4-5 set
+// 0x2000-0x3FFF  ROM bank, 0 reads as 1
+// 0x4000-0x5FFF  ROM bank mask, bits taken from the base bank, same condition as the base bank
+//
+// The carts store their own logo and get past the boot ROM's logo check by scrambling address
+// lines while it runs, so the check sees a Nintendo logo hidden among the header bytes. The MMC2
+// doesn't scramble for the CGB boot ROM, which its games also run under.
+pub struct Sachen {
+    rom: Vec<u8>,
+    mmc2: bool,
+    base_bank: u8,
+    rom_bank: u8,
+    bank_mask: u8,
+    scrambled: bool,
+}
+
+impl Sachen {
+    pub fn new(rom: Vec<u8>, mmc2: bool) -> Sachen {
+        Sachen {
+            rom,
+            mmc2,
+            base_bank: 0,
+            rom_bank: 1,
+            bank_mask: 0,
+            scrambled: false,
+        }
+    }
+
+    fn registers_unlocked(&self) -> bool {
+        self.rom_bank & 0x30 == 0x30
+    }
+}
+
+impl Cartridge for Sachen {
+    fn read_rom(&self, address: u16) -> u8 {
+        let base_bank = self.base_bank & self.bank_mask;
+        let (bank, address) = match address {
+            0x0000..=0x3fff if self.scrambled => (base_bank, scramble(address)),
+            0x0000..=0x3fff => (base_bank, address),
+            _ => (base_bank | (self.rom_bank & !self.bank_mask), address),
+        };
+        self.rom[rom_offset(&self.rom, bank as usize, address)]
+    }
+
+    fn write_rom(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1fff if self.registers_unlocked() => self.base_bank = value,
+            0x2000..=0x3fff => self.rom_bank = if value == 0 { 1 } else { value },
+            0x4000..=0x5fff if self.registers_unlocked() => self.bank_mask = value,
+            _ => {}
+        }
+    }
+
+    // There is no external RAM.
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xff
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+
+    fn set_boot_rom(&mut self, boot_rom: Option<&BootRom>) {
+        self.scrambled = boot_rom.is_some_and(|boot_rom| !(self.mmc2 && boot_rom.is_cgb()));
+    }
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_u8(self.base_bank);
+        state.write_u8(self.rom_bank);
+        state.write_u8(self.bank_mask);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let base_bank = state.read_u8()?;
+        let rom_bank = state.read_u8()?;
+        let bank_mask = state.read_u8()?;
+        state.finish()?;
+
+        self.base_bank = base_bank;
+        self.rom_bank = rom_bank;
+        self.bank_mask = bank_mask;
+        Ok(())
+    }
+}
+
+// Where a read lands while scrambled: in 0x0100-0x01FF, address lines 0 and 6 and lines 1 and 4
+// are swapped.
+pub fn scramble(address: u16) -> u16 {
+    if address & 0xff00 != 0x0100 {
+        return address;
+    }
+    let swap = |address: u16, a: u16, b: u16| {
+        let differ = ((address >> a) ^ (address >> b)) & 1;
+        address ^ (differ << a | differ << b)
+    };
+    swap(swap(address, 0, 6), 1, 4)
+}
@@ -0,0 +1,121 @@
+use crate::error::EmuError;
+
+// Builds a cartridge's save state as a flat stream of little-endian values.
+pub struct StateWriter {
+    data: Vec<u8>,
+}
+
+impl StateWriter {
+    pub fn new() -> StateWriter {
+        StateWriter { data: Vec::new() }
+    }
+
+    pub fn write_u8(&mut self, value: u8) {
+        self.data.push(value);
+    }
+
+    pub fn write_bool(&mut self, value: bool) {
+        self.data.push(value as u8);
+    }
+
+    pub fn write_u16(&mut self, value: u16) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u32(&mut self, value: u32) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    pub fn write_u64(&mut self, value: u64) {
+        self.data.extend_from_slice(&value.to_le_bytes());
+    }
+
+    // Prefixed with its length, so it can be checked on the way back in.
+    pub fn write_bytes(&mut self, bytes: &[u8]) {
+        self.write_u32(bytes.len() as u32);
+        self.data.extend_from_slice(bytes);
+    }
+
+    pub fn finish(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl Default for StateWriter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// Reads back what a `StateWriter` wrote, in the same order.
+pub struct StateReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> StateReader<'a> {
+    pub fn new(data: &'a [u8]) -> StateReader<'a> {
+        StateReader { data, position: 0 }
+    }
+
+    pub fn read_u8(&mut self) -> Result<u8, EmuError> {
+        Ok(self.take(1)?[0])
+    }
+
+    pub fn read_bool(&mut self) -> Result<bool, EmuError> {
+        Ok(self.read_u8()? != 0)
+    }
+
+    pub fn read_u16(&mut self) -> Result<u16, EmuError> {
+        Ok(u16::from_le_bytes(self.take(2)?.try_into().unwrap()))
+    }
+
+    pub fn read_u32(&mut self) -> Result<u32, EmuError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+    }
+
+    pub fn read_u64(&mut self) -> Result<u64, EmuError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+
+    // Fills `bytes`, which must be exactly as long as what was written.
+    pub fn read_bytes_into(&mut self, bytes: &mut [u8]) -> Result<(), EmuError> {
+        let length = self.read_u32()? as usize;
+        if length != bytes.len() {
+            return Err(EmuError::InvalidState {
+                reason: "memory size differs",
+            });
+        }
+        bytes.copy_from_slice(self.take(length)?);
+        Ok(())
+    }
+
+    // Same, into a new buffer of the given length.
+    pub fn read_bytes(&mut self, length: usize) -> Result<Vec<u8>, EmuError> {
+        let mut bytes = vec![0; length];
+        self.read_bytes_into(&mut bytes)?;
+        Ok(bytes)
+    }
+
+    // Checks that the whole state was used.
+    pub fn finish(self) -> Result<(), EmuError> {
+        if self.position != self.data.len() {
+            return Err(EmuError::InvalidState {
+                reason: "unexpected trailing data",
+            });
+        }
+        Ok(())
+    }
+
+    fn take(&mut self, length: usize) -> Result<&'a [u8], EmuError> {
+        let end = self.position + length;
+        if end > self.data.len() {
+            return Err(EmuError::InvalidState {
+                reason: "state is truncated",
+            });
+        }
+        let bytes = &self.data[self.position..end];
+        self.position = end;
+        Ok(bytes)
+    }
+}
@@ -0,0 +1,52 @@
+use super::state::{StateReader, StateWriter};
+use super::Cartridge;
+use crate::error::EmuError;
+
+// Wisdom Tree's mapper. It switches the whole ROM area between 32 KiB banks, taking the bank
+// number from the address written to rather than the value. The headers say ROM only.
+//
+// 0x0000-0x3FFF  32 KiB ROM bank, the lower 8 bits of the address
+pub struct WisdomTree {
+    rom: Vec<u8>,
+    rom_bank: u8,
+}
+
+impl WisdomTree {
+    pub fn new(rom: Vec<u8>) -> WisdomTree {
+        WisdomTree { rom, rom_bank: 0 }
+    }
+}
+
+impl Cartridge for WisdomTree {
+    fn read_rom(&self, address: u16) -> u8 {
+        self.rom[(self.rom_bank as usize * 0x8000 + address as usize) % self.rom.len()]
+    }
+
+    fn write_rom(&mut self, address: u16, _value: u8) {
+        if address < 0x4000 {
+            self.rom_bank = address as u8;
+        }
+    }
+
+    // There is no external RAM.
+    fn read_ram(&self, _address: u16) -> u8 {
+        0xff
+    }
+
+    fn write_ram(&mut self, _address: u16, _value: u8) {}
+
+    fn save_state(&self) -> Vec<u8> {
+        let mut state = StateWriter::new();
+        state.write_u8(self.rom_bank);
+        state.finish()
+    }
+
+    fn load_state(&mut self, state: &[u8]) -> Result<(), EmuError> {
+        let mut state = StateReader::new(state);
+        let rom_bank = state.read_u8()?;
+        state.finish()?;
+
+        self.rom_bank = rom_bank;
+        Ok(())
+    }
+}
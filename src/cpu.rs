@@ -1,453 +1,1622 @@
 pub mod flag_registers;
 pub mod instructions;
+pub mod interrupts;
 pub mod memory_bus;
 pub mod registers;
 
-use flag_registers::FlagsRegister;
 use instructions::Instruction;
-use memory_bus::MemoryBus;
+use interrupts::Interrupt;
+use memory_bus::{MemoryBus, RumbleCallback, ToneCallback, CYCLES_PER_LINE};
 use registers::Registers;
 
+use std::rc::Rc;
+
+use crate::boot_rom::BootRom;
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::pocket_camera::image::ImageSource;
+use crate::cartridge::rtc::RtcMode;
+use crate::cartridge::{self, Cartridge};
+use crate::error::EmuError;
+use crate::infrared::Infrared;
+use crate::joypad::Button;
+use crate::model::Model;
+use crate::save::SaveFile;
+
+// One second of emulated time, how long battery RAM may stay dirty before it is flushed.
+const SAVE_FLUSH_CYCLES: u32 = 4_194_304;
+
+// When the rest of the machine is advanced relative to the CPU.
+pub enum TimingMode {
+    // Peripherals catch up once the whole instruction has executed.
+    Instruction,
+    // Every memory access takes one M-cycle (4 T-cycles) and advances the timer, LCD and DMA before
+    // it happens, so peripherals observe accesses at the right point inside an instruction.
+    MCycle,
+}
+
+// What happens when the CPU fetches one of the opcodes that hang real hardware.
+pub enum IllegalOpcodePolicy {
+    // Freeze the CPU like real hardware does, while the timer and LCD keep running.
+    LockUp,
+    // Return `EmuError::UnknownOpcode` from `step`, with the cycles spent fetching the opcode.
+    Error,
+    // Return `EmuError::Breakpoint` from `step`, leaving PC on the opcode so the machine can be
+    // inspected.
+    Break,
+}
+
 pub struct CPU {
     registers: Registers,
     sp: u16,
     pc: u16,
-    flags_register: FlagsRegister,
     bus: MemoryBus,
+    ime: bool,
+    // Set by EI; IME is only enabled once the instruction after EI has executed.
+    ime_scheduled: bool,
+    // HALT suspends the CPU until an interrupt is pending; STOP also stops the LCD and waits for a
+    // button press.
+    halted: bool,
+    stopped: bool,
+    // Set when HALT runs with IME=0 and an interrupt already pending: the next opcode is read
+    // without advancing PC, so the byte after HALT is executed twice.
+    halt_bug: bool,
+    // Set after an illegal opcode under `IllegalOpcodePolicy::LockUp`. Nothing gets the CPU out of
+    // it.
+    locked: bool,
+
+    illegal_opcode_policy: IllegalOpcodePolicy,
+    timing_mode: TimingMode,
+    // T-cycles elapsed so far in the instruction being executed.
+    instruction_cycles: u32,
 
-    cycles: u32,
-    last_ly_update: u32,
+    // Where battery RAM is kept, and the T-cycles run since it was first written after the last
+    // flush.
+    save_file: Option<SaveFile>,
+    save_cycles: u32,
+    // The last periodic flush that failed, until `take_save_error` picks it up.
+    save_error: Option<EmuError>,
 }
 
 impl CPU {
-    pub fn step(&mut self) {
+    // Runs a single instruction, or dispatches a pending interrupt instead, and returns the number
+    // of T-cycles it took.
+    pub fn step(&mut self) -> Result<u32, EmuError> {
+        self.instruction_cycles = 0;
+
+        // Only a selected key being held wakes the CPU from STOP. The joypad interrupt isn't
+        // needed.
+        if self.stopped && self.bus.joypad_input_low() {
+            self.stopped = false;
+        }
+
+        if self.halted && self.bus.pending_interrupts() != 0 {
+            self.halted = false;
+        }
+
+        if self.stopped {
+            // The keys are checked again after a line, which the rest of the machine still runs for.
+            self.advance(CYCLES_PER_LINE);
+        } else if self.locked || self.halted {
+            // Nothing can wake the CPU before the next timer or LCD event, so skip straight to it.
+            let cycles = self.bus.cycles_until_next_event();
+            self.advance(cycles);
+        } else if self.ime && self.bus.pending_interrupts() != 0 {
+            self.dispatch_interrupt();
+        } else {
+            let enable_ime = self.ime_scheduled;
+            if let Err(error) = self.fetch_and_execute() {
+                // The fetch still took its cycles, which the error reports, so the rest of the
+                // machine is brought up to date with them first.
+                if let TimingMode::Instruction = self.timing_mode {
+                    self.bus.tick(self.instruction_cycles);
+                }
+                return Err(error);
+            }
+            if enable_ime && self.ime_scheduled {
+                self.ime = true;
+                self.ime_scheduled = false;
+            }
+        }
+
+        if let TimingMode::Instruction = self.timing_mode {
+            self.bus.tick(self.instruction_cycles);
+        }
+
+        // Batch up writes to battery RAM and flush them a second of emulated time after the
+        // first one.
+        if self.save_file.is_some() && self.bus.save_dirty() {
+            self.save_cycles += self.instruction_cycles;
+            if self.save_cycles >= SAVE_FLUSH_CYCLES {
+                // Returning the error would lose the cycles just run, so it is kept aside instead
+                // and the flush is tried again a second later.
+                if let Err(error) = self.flush_save() {
+                    self.save_cycles = 0;
+                    self.save_error = Some(error);
+                }
+            }
+        }
+
+        Ok(self.instruction_cycles)
+    }
+
+    pub fn set_illegal_opcode_policy(&mut self, illegal_opcode_policy: IllegalOpcodePolicy) {
+        self.illegal_opcode_policy = illegal_opcode_policy;
+    }
+
+    pub fn set_timing_mode(&mut self, timing_mode: TimingMode) {
+        self.timing_mode = timing_mode;
+    }
+
+    fn fetch_and_execute(&mut self) -> Result<(), EmuError> {
         let old_pc = self.pc;
-        let mut instruction_byte = self.bus.read_byte(self.pc);
-        self.pc = self.pc.wrapping_add(1);
+        let mut instruction_byte = self.read_next_byte();
+        if self.halt_bug {
+            self.halt_bug = false;
+            self.pc = self.pc.wrapping_sub(1);
+        }
         let prefixed = instruction_byte == 0xCB;
         if prefixed {
-            instruction_byte = self.bus.read_byte(self.pc);
-            self.pc = self.pc.wrapping_add(1);
+            instruction_byte = self.read_next_byte();
         }
-        println!("Running instruction {:x} @ {}", instruction_byte, old_pc);
         if let Some(instruction) = Instruction::from_byte(instruction_byte, prefixed) {
-            self.execute(instruction)
-        } else {
-            let description = format!(
-                "0x{}{:x}",
-                if prefixed { "cb" } else { "" },
-                instruction_byte
-            );
-            panic!("Unknown instruction found for: {}", description)
-        };
-
-        self.cycles += 1;
+            self.execute(instruction);
+            return Ok(());
+        }
 
-        if self.last_ly_update + 114 < self.cycles {
-            let mut ly = self.bus.read_ly();
-            ly += 1;
-            if ly > 154 {
-                ly = 0;
+        // Every CB-prefixed opcode is valid, so only the unprefixed page gets here.
+        match self.illegal_opcode_policy {
+            IllegalOpcodePolicy::LockUp => {
+                self.locked = true;
+                Ok(())
+            }
+            IllegalOpcodePolicy::Error => {
+                self.pc = old_pc;
+                Err(EmuError::UnknownOpcode {
+                    pc: old_pc,
+                    opcode: instruction_byte,
+                    prefixed,
+                    cycles: self.instruction_cycles,
+                })
+            }
+            IllegalOpcodePolicy::Break => {
+                self.pc = old_pc;
+                Err(EmuError::Breakpoint {
+                    pc: old_pc,
+                    opcode: instruction_byte,
+                    cycles: self.instruction_cycles,
+                })
             }
-            self.bus.write_ly(ly);
-            self.last_ly_update = self.cycles;
         }
     }
 
+    // Takes 5 M-cycles: two idle ones, pushing PC and jumping to the handler. The handler is only
+    // chosen after the high byte of PC has been pushed, so when that push lands on IE (SP=0x0000)
+    // it can cancel the dispatch, in which case the CPU jumps to 0x0000 instead.
+    fn dispatch_interrupt(&mut self) {
+        self.ime = false;
+        self.ime_scheduled = false;
+        self.internal_cycle();
+        self.internal_cycle();
+
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, (self.pc >> 8) as u8);
+        let interrupt = Interrupt::highest_priority(self.bus.pending_interrupts());
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, (self.pc & 0xff) as u8);
+
+        self.pc = match interrupt {
+            Some(interrupt) => {
+                self.bus.acknowledge_interrupt(interrupt);
+                interrupt.handler_address()
+            }
+            None => 0x0000,
+        };
+        self.internal_cycle();
+    }
+
+    // Executes the instruction after its opcode has been fetched. The T-cycles it takes are
+    // accumulated through `read_byte`, `write_byte` and `internal_cycle`.
     fn execute(&mut self, instruction: Instruction) {
         match instruction {
+            Instruction::ADC(source) => {
+                let value = self.read_arithmetic_source(source);
+                self.add(value, self.registers.f.carry);
+            }
             Instruction::ADD(source) => {
+                let value = self.read_arithmetic_source(source);
+                self.add(value, false);
+            }
+            Instruction::ADDHL(source) => {
                 let source_value = match source {
-                    instructions::ArithmeticSource::HL_ => {
-                        self.bus.read_byte(self.registers.get_hl())
-                    }
-                    _ => {
-                        panic!("TODO: implement other sources")
-                    }
+                    instructions::AddHLSource::BC => self.registers.get_bc(),
+                    instructions::AddHLSource::DE => self.registers.get_de(),
+                    instructions::AddHLSource::HL => self.registers.get_hl(),
+                    instructions::AddHLSource::SP => self.sp,
                 };
-
-                let (sum, did_overflow) = self.registers.a.overflowing_add(source_value);
-                self.flags_register.zero = sum == 0;
-                self.flags_register.subtract = false;
-                self.flags_register.carry = did_overflow;
-                self.flags_register.half_carry =
-                    (self.registers.a & 0x0f) + (source_value & 0x0f) > 0xf;
-                self.registers.a = sum;
+                let hl = self.registers.get_hl();
+                let (sum, did_overflow) = hl.overflowing_add(source_value);
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = (hl & 0xfff) + (source_value & 0xfff) > 0xfff;
+                self.registers.f.carry = did_overflow;
+                self.registers.set_hl(sum);
+                self.internal_cycle();
+            }
+            Instruction::ADDSP => {
+                let offset = self.read_next_byte() as i8;
+                self.sp = self.add_sp_offset(offset);
+                self.internal_cycle();
+                self.internal_cycle();
+            }
+            Instruction::AND(source) => {
+                let value = self.read_arithmetic_source(source);
+                self.registers.a &= value;
+                self.registers.f.zero = self.registers.a == 0;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = true;
+                self.registers.f.carry = false;
             }
             Instruction::BIT(number, source) => {
-                let source_value = match source {
-                    instructions::BitSource::H => self.registers.h,
-                    _ => {
-                        panic!("TODO: implement other sources")
-                    }
-                };
-                self.flags_register.zero = (source_value >> number) & 1 == 0;
-                self.flags_register.subtract = false;
-                self.flags_register.half_carry = true;
+                let source_value = self.read_bit_source(source);
+                self.registers.f.zero = (source_value >> number) & 1 == 0;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = true;
             }
-            Instruction::CALL => {
-                let least_significant_byte = self.bus.read_byte(self.pc) as u16;
-                let most_significant_byte = self.bus.read_byte(self.pc + 1) as u16;
-                let value = (most_significant_byte << 8) | least_significant_byte;
-
-                self.pc = self.pc.wrapping_add(2);
-
-                self.sp = self.sp.wrapping_sub(1);
-                self.bus.write_byte(self.sp, (self.pc >> 8) as u8);
-                self.sp = self.sp.wrapping_sub(1);
-                self.bus.write_byte(self.sp, (self.pc & 0xff) as u8);
-
-                println!("Calling 0x{:x}", value);
-                self.pc = value;
+            Instruction::CALL(condition) => {
+                let value = self.read_next_word();
+                if self.check_jump_condition(condition) {
+                    self.internal_cycle();
+                    self.push(self.pc);
+                    self.pc = value;
+                }
+            }
+            Instruction::CCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = !self.registers.f.carry;
             }
             Instruction::CP(source) => {
                 let source_value = match source {
-                    instructions::CompareSource::N8 => {
-                        let old_pc = self.pc;
-                        self.pc = self.pc.wrapping_add(1);
-                        self.bus.read_byte(old_pc)
-                    }
+                    instructions::CompareSource::A => self.registers.a,
+                    instructions::CompareSource::B => self.registers.b,
+                    instructions::CompareSource::C => self.registers.c,
+                    instructions::CompareSource::D => self.registers.d,
+                    instructions::CompareSource::E => self.registers.e,
+                    instructions::CompareSource::H => self.registers.h,
+                    instructions::CompareSource::L => self.registers.l,
+                    instructions::CompareSource::N8 => self.read_next_byte(),
                     instructions::CompareSource::HL_ => {
                         let hl = self.registers.get_hl();
-                        self.bus.read_byte(hl)
+                        self.read_byte(hl)
                     }
                 };
 
-                self.flags_register.zero = self.registers.a == source_value;
-                self.flags_register.subtract = true;
-                self.flags_register.half_carry = (self.registers.a & 0xf) < (source_value & 0xf);
-                self.flags_register.carry = self.registers.a < source_value;
+                self.registers.f.zero = self.registers.a == source_value;
+                self.registers.f.subtract = true;
+                self.registers.f.half_carry = (self.registers.a & 0xf) < (source_value & 0xf);
+                self.registers.f.carry = self.registers.a < source_value;
+            }
+            Instruction::CPL => {
+                self.registers.a = !self.registers.a;
+                self.registers.f.subtract = true;
+                self.registers.f.half_carry = true;
+            }
+            Instruction::DAA => {
+                // Corrects A back to binary-coded decimal after an ADD/ADC (N=0) or SUB/SBC (N=1).
+                // H and C record which digits carried or borrowed; after an addition the digits can
+                // also be out of range without a carry.
+                let mut adjustment = 0;
+                let mut carry = self.registers.f.carry;
+                if self.registers.f.subtract {
+                    if self.registers.f.half_carry {
+                        adjustment |= 0x06;
+                    }
+                    if carry {
+                        adjustment |= 0x60;
+                    }
+                    self.registers.a = self.registers.a.wrapping_sub(adjustment);
+                } else {
+                    if self.registers.f.half_carry || self.registers.a & 0xf > 0x9 {
+                        adjustment |= 0x06;
+                    }
+                    if carry || self.registers.a > 0x99 {
+                        adjustment |= 0x60;
+                        carry = true;
+                    }
+                    self.registers.a = self.registers.a.wrapping_add(adjustment);
+                }
+                self.registers.f.zero = self.registers.a == 0;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = carry;
             }
             Instruction::DEC(target) => {
+                let value = match target {
+                    instructions::DecrementTarget::A => self.registers.a,
+                    instructions::DecrementTarget::B => self.registers.b,
+                    instructions::DecrementTarget::C => self.registers.c,
+                    instructions::DecrementTarget::D => self.registers.d,
+                    instructions::DecrementTarget::E => self.registers.e,
+                    instructions::DecrementTarget::H => self.registers.h,
+                    instructions::DecrementTarget::L => self.registers.l,
+                    instructions::DecrementTarget::HL_ => self.read_byte(self.registers.get_hl()),
+                    instructions::DecrementTarget::BC => {
+                        let bc = self.registers.get_bc();
+                        self.registers.set_bc(bc.wrapping_sub(1));
+                        self.internal_cycle();
+                        return;
+                    }
+                    instructions::DecrementTarget::DE => {
+                        let de = self.registers.get_de();
+                        self.registers.set_de(de.wrapping_sub(1));
+                        self.internal_cycle();
+                        return;
+                    }
+                    instructions::DecrementTarget::HL => {
+                        let hl = self.registers.get_hl();
+                        self.registers.set_hl(hl.wrapping_sub(1));
+                        self.internal_cycle();
+                        return;
+                    }
+                    instructions::DecrementTarget::SP => {
+                        self.sp = self.sp.wrapping_sub(1);
+                        self.internal_cycle();
+                        return;
+                    }
+                };
+
+                let result = value.wrapping_sub(1);
+                self.registers.f.zero = result == 0;
+                self.registers.f.subtract = true;
+                self.registers.f.half_carry = value & 0xf == 0;
+
                 match target {
-                    instructions::DecrementTarget::A => {
-                        self.flags_register.half_carry = self.registers.a == 0;
-                        self.registers.a = self.registers.a.wrapping_sub(1);
-                        self.flags_register.zero = self.registers.a == 0;
-                        self.flags_register.subtract = true;
-                    }
-                    instructions::DecrementTarget::B => {
-                        self.flags_register.half_carry = self.registers.b == 0;
-                        self.registers.b = self.registers.b.wrapping_sub(1);
-                        self.flags_register.zero = self.registers.b == 0;
-                        self.flags_register.subtract = true;
-                    }
-                    instructions::DecrementTarget::C => {
-                        self.flags_register.half_carry = self.registers.c == 0;
-                        self.registers.c = self.registers.c.wrapping_sub(1);
-                        self.flags_register.zero = self.registers.c == 0;
-                        self.flags_register.subtract = true;
-                    }
-                    instructions::DecrementTarget::D => {
-                        self.flags_register.half_carry = self.registers.d == 0;
-                        self.registers.d = self.registers.d.wrapping_sub(1);
-                        self.flags_register.zero = self.registers.d == 0;
-                        self.flags_register.subtract = true;
-                    }
-                    instructions::DecrementTarget::E => {
-                        self.flags_register.half_carry = self.registers.e == 0;
-                        self.registers.e = self.registers.e.wrapping_sub(1);
-                        self.flags_register.zero = self.registers.e == 0;
-                        self.flags_register.subtract = true;
-                    }
-                    _ => {
-                        panic!("TODO: implement other targets")
+                    instructions::DecrementTarget::A => self.registers.a = result,
+                    instructions::DecrementTarget::B => self.registers.b = result,
+                    instructions::DecrementTarget::C => self.registers.c = result,
+                    instructions::DecrementTarget::D => self.registers.d = result,
+                    instructions::DecrementTarget::E => self.registers.e = result,
+                    instructions::DecrementTarget::H => self.registers.h = result,
+                    instructions::DecrementTarget::L => self.registers.l = result,
+                    instructions::DecrementTarget::HL_ => {
+                        self.write_byte(self.registers.get_hl(), result)
                     }
+                    _ => unreachable!(),
                 };
             }
+            Instruction::DI => {
+                self.ime = false;
+                self.ime_scheduled = false;
+            }
+            Instruction::EI => {
+                self.ime_scheduled = true;
+            }
+            Instruction::HALT => {
+                if self.bus.pending_interrupts() == 0 {
+                    self.halted = true;
+                } else if self.ime_scheduled {
+                    // With EI right before HALT the interrupt is dispatched first and returns to
+                    // the HALT, which then runs again.
+                    self.pc = self.pc.wrapping_sub(1);
+                } else if !self.ime {
+                    self.halt_bug = true;
+                }
+            }
             Instruction::INC(target) => {
-                match target {
-                    instructions::IncTarget::B => {
-                        self.registers.b = self.registers.b.wrapping_add(1);
-                        self.flags_register.zero = self.registers.b == 0;
-                        self.flags_register.subtract = false;
-                        self.flags_register.half_carry = self.registers.b == 0x10;
-                    }
-                    instructions::IncTarget::C => {
-                        self.registers.c = self.registers.c.wrapping_add(1);
-                        self.flags_register.zero = self.registers.c == 0;
-                        self.flags_register.subtract = false;
-                        self.flags_register.half_carry = self.registers.c == 0x10;
-                    }
-                    instructions::IncTarget::H => {
-                        self.registers.h = self.registers.h.wrapping_add(1);
-                        self.flags_register.zero = self.registers.h == 0;
-                        self.flags_register.subtract = false;
-                        self.flags_register.half_carry = self.registers.h == 0x10;
+                let value = match target {
+                    instructions::IncTarget::A => self.registers.a,
+                    instructions::IncTarget::B => self.registers.b,
+                    instructions::IncTarget::C => self.registers.c,
+                    instructions::IncTarget::D => self.registers.d,
+                    instructions::IncTarget::E => self.registers.e,
+                    instructions::IncTarget::H => self.registers.h,
+                    instructions::IncTarget::L => self.registers.l,
+                    instructions::IncTarget::HL_ => self.read_byte(self.registers.get_hl()),
+                    instructions::IncTarget::BC => {
+                        let bc = self.registers.get_bc();
+                        self.registers.set_bc(bc.wrapping_add(1));
+                        self.internal_cycle();
+                        return;
                     }
                     instructions::IncTarget::DE => {
                         let de = self.registers.get_de();
                         self.registers.set_de(de.wrapping_add(1));
+                        self.internal_cycle();
+                        return;
                     }
                     instructions::IncTarget::HL => {
                         let hl = self.registers.get_hl();
                         self.registers.set_hl(hl.wrapping_add(1));
+                        self.internal_cycle();
+                        return;
                     }
-                    _ => {
-                        panic!("TODO: implement other targets")
+                    instructions::IncTarget::SP => {
+                        self.sp = self.sp.wrapping_add(1);
+                        self.internal_cycle();
+                        return;
                     }
                 };
-            }
-            Instruction::JR(condition) => {
-                let condition_value = match condition {
-                    instructions::JumpCondition::Always => true,
-                    instructions::JumpCondition::NZ => !self.flags_register.zero,
-                    instructions::JumpCondition::Z => self.flags_register.zero,
-                    _ => {
-                        panic!("TODO: implement other sources")
+
+                let result = value.wrapping_add(1);
+                self.registers.f.zero = result == 0;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = result & 0xf == 0;
+
+                match target {
+                    instructions::IncTarget::A => self.registers.a = result,
+                    instructions::IncTarget::B => self.registers.b = result,
+                    instructions::IncTarget::C => self.registers.c = result,
+                    instructions::IncTarget::D => self.registers.d = result,
+                    instructions::IncTarget::E => self.registers.e = result,
+                    instructions::IncTarget::H => self.registers.h = result,
+                    instructions::IncTarget::L => self.registers.l = result,
+                    instructions::IncTarget::HL_ => {
+                        self.write_byte(self.registers.get_hl(), result)
                     }
+                    _ => unreachable!(),
                 };
-                if condition_value {
-                    let distance = self.bus.read_signed_byte(self.pc);
-                    println!("Jumping {}", distance);
-                    self.pc = self.pc.wrapping_add(1).wrapping_add_signed(distance.into());
-                } else {
-                    println!("Not jumping");
-                    // Optimization: skip reading the next byte since we are not jumping, still need to update pc counter though.
-                    self.pc = self.pc.wrapping_add(1);
+            }
+            Instruction::JP(condition) => {
+                let value = self.read_next_word();
+                if self.check_jump_condition(condition) {
+                    self.internal_cycle();
+                    self.pc = value;
+                }
+            }
+            Instruction::JPHL => {
+                self.pc = self.registers.get_hl();
+            }
+            Instruction::JR(condition) => {
+                // The offset is fetched whether or not the jump is taken.
+                let distance = self.read_next_byte() as i8;
+                if self.check_jump_condition(condition) {
+                    self.internal_cycle();
+                    self.pc = self.pc.wrapping_add_signed(distance.into());
                 }
             }
             Instruction::LD(target, source) => {
                 let source_value = match source {
                     instructions::LoadSource::A => self.registers.a,
                     instructions::LoadSource::B => self.registers.b,
+                    instructions::LoadSource::C => self.registers.c,
+                    instructions::LoadSource::D => self.registers.d,
                     instructions::LoadSource::E => self.registers.e,
                     instructions::LoadSource::H => self.registers.h,
                     instructions::LoadSource::L => self.registers.l,
-                    instructions::LoadSource::DE_ => self.bus.read_byte(self.registers.get_de()),
-                    instructions::LoadSource::N8 => {
-                        let old_pc = self.pc;
-                        self.pc = self.pc.wrapping_add(1);
-                        self.bus.read_byte(old_pc)
+                    instructions::LoadSource::BC_ => self.read_byte(self.registers.get_bc()),
+                    instructions::LoadSource::DE_ => self.read_byte(self.registers.get_de()),
+                    instructions::LoadSource::HL_ => self.read_byte(self.registers.get_hl()),
+                    instructions::LoadSource::HLD => {
+                        let hl = self.registers.get_hl();
+                        self.registers.set_hl(hl.wrapping_sub(1));
+                        self.read_byte(hl)
+                    }
+                    instructions::LoadSource::HLI => {
+                        let hl = self.registers.get_hl();
+                        self.registers.set_hl(hl.wrapping_add(1));
+                        self.read_byte(hl)
                     }
-                    _ => {
-                        panic!("TODO: implement other sources")
+                    instructions::LoadSource::N8 => self.read_next_byte(),
+                    instructions::LoadSource::N16_ => {
+                        let address = self.read_next_word();
+                        self.read_byte(address)
                     }
                 };
                 match target {
                     instructions::LoadTarget::A => {
-                        println!("Setting a={}", source_value);
                         self.registers.a = source_value;
                     }
                     instructions::LoadTarget::B => {
-                        println!("Setting b={}", source_value);
                         self.registers.b = source_value;
                     }
                     instructions::LoadTarget::C => {
-                        println!("Setting c={}", source_value);
                         self.registers.c = source_value;
                     }
                     instructions::LoadTarget::D => {
-                        println!("Setting d={}", source_value);
                         self.registers.d = source_value;
                     }
                     instructions::LoadTarget::E => {
-                        println!("Setting e={}", source_value);
                         self.registers.e = source_value;
                     }
                     instructions::LoadTarget::H => {
-                        println!("Setting h={}", source_value);
                         self.registers.h = source_value;
                     }
                     instructions::LoadTarget::L => {
-                        println!("Setting l={}", source_value);
                         self.registers.l = source_value;
                     }
+                    instructions::LoadTarget::BC_ => {
+                        let bc = self.registers.get_bc();
+                        self.write_byte(bc, source_value);
+                    }
+                    instructions::LoadTarget::DE_ => {
+                        let de = self.registers.get_de();
+                        self.write_byte(de, source_value);
+                    }
                     instructions::LoadTarget::HL_ => {
                         let hl = self.registers.get_hl();
-                        self.bus.write_byte(hl, source_value);
-                        println!("Setting {:x}={}", hl, source_value);
+                        self.write_byte(hl, source_value);
                     }
                     instructions::LoadTarget::HLD => {
                         let hl = self.registers.get_hl();
-                        self.bus.write_byte(hl, source_value);
-                        println!("Setting {:x}={}", hl, source_value);
-                        self.registers.set_hl(hl - 1);
+                        self.write_byte(hl, source_value);
+                        self.registers.set_hl(hl.wrapping_sub(1));
                     }
                     instructions::LoadTarget::HLI => {
                         let hl = self.registers.get_hl();
-                        self.bus.write_byte(hl, source_value);
-                        println!("Setting {:x}={}", hl, source_value);
-                        self.registers.set_hl(hl + 1);
+                        self.write_byte(hl, source_value);
+                        self.registers.set_hl(hl.wrapping_add(1));
                     }
                     instructions::LoadTarget::N16_ => {
-                        let least_significant_byte = self.bus.read_byte(self.pc) as u16;
-                        let most_significant_byte = self.bus.read_byte(self.pc + 1) as u16;
-                        let value = (most_significant_byte << 8) | least_significant_byte;
-
-                        self.pc = self.pc.wrapping_add(2);
-
-                        println!("Setting {:x}={}", value, source_value);
-                        self.bus.write_byte(value, source_value);
-                    }
-                    _ => {
-                        panic!("TODO: implement other targets")
+                        let value = self.read_next_word();
+                        self.write_byte(value, source_value);
                     }
                 };
             }
             Instruction::LDH(target, source) => {
                 let source_value = match source {
                     instructions::LoadHSource::A => self.registers.a,
-                    instructions::LoadHSource::N8_ => {
-                        let n8 = self.bus.read_byte(self.pc);
-                        self.pc = self.pc.wrapping_add(1);
-                        self.bus.read_byte(0xff00 + (n8 as u16))
+                    instructions::LoadHSource::C_ => {
+                        self.read_byte(0xff00 + (self.registers.c as u16))
                     }
-                    _ => {
-                        panic!("TODO: implement other sources")
+                    instructions::LoadHSource::N8_ => {
+                        let n8 = self.read_next_byte();
+                        self.read_byte(0xff00 + (n8 as u16))
                     }
                 };
                 match target {
                     instructions::LoadHTarget::A => {
-                        println!("LDH a={}", source_value);
                         self.registers.a = source_value;
                     }
                     instructions::LoadHTarget::C_ => {
-                        println!("LDH 0xff{:x}={}", self.registers.c, source_value);
-                        self.bus
-                            .write_byte(0xff00 + (self.registers.c as u16), source_value);
+                        self.write_byte(0xff00 + (self.registers.c as u16), source_value);
                     }
                     instructions::LoadHTarget::N8_ => {
-                        let n8 = self.bus.read_byte(self.pc);
-                        println!("LDH 0xff{:x}={}", n8, source_value);
-                        self.bus.write_byte(0xff00 + (n8 as u16), source_value);
-                        self.pc = self.pc.wrapping_add(1);
-                    }
-                    _ => {
-                        panic!("TODO: implement other targets")
+                        let n8 = self.read_next_byte();
+                        self.write_byte(0xff00 + (n8 as u16), source_value);
                     }
                 };
             }
+            Instruction::LDHLSP => {
+                let offset = self.read_next_byte() as i8;
+                let value = self.add_sp_offset(offset);
+                self.registers.set_hl(value);
+                self.internal_cycle();
+            }
             Instruction::LDN16(target) => {
-                let least_significant_byte = self.bus.read_byte(self.pc) as u16;
-                let most_significant_byte = self.bus.read_byte(self.pc + 1) as u16;
-                let value = (most_significant_byte << 8) | least_significant_byte;
-                self.pc = self.pc.wrapping_add(2);
+                let value = self.read_next_word();
 
                 match target {
+                    instructions::LoadTypeN16::BC => {
+                        self.registers.set_bc(value);
+                    }
                     instructions::LoadTypeN16::DE => {
                         self.registers.set_de(value);
                     }
-                    instructions::LoadTypeN16::SP => {
-                        self.sp = value;
-                    }
                     instructions::LoadTypeN16::HL => {
                         self.registers.set_hl(value);
                     }
-                    _ => {
-                        panic!("Unknown target.");
+                    instructions::LoadTypeN16::SP => {
+                        self.sp = value;
                     }
                 }
             }
+            Instruction::LDN16SP => {
+                let address = self.read_next_word();
+                self.write_byte(address, (self.sp & 0xff) as u8);
+                self.write_byte(address.wrapping_add(1), (self.sp >> 8) as u8);
+            }
+            Instruction::LDSPHL => {
+                self.sp = self.registers.get_hl();
+                self.internal_cycle();
+            }
             Instruction::NOP => {}
-            Instruction::POP(target) => match target {
-                instructions::PopTarget::BC => {
-                    self.registers.c = self.bus.read_byte(self.sp);
-                    self.sp = self.sp.wrapping_add(1);
-                    self.registers.b = self.bus.read_byte(self.sp);
-                    self.sp = self.sp.wrapping_add(1);
-                }
-                _ => {
-                    panic!("Unknown target.");
-                }
-            },
-            Instruction::PUSH(target) => match target {
-                instructions::PushTarget::BC => {
-                    self.sp = self.sp.wrapping_sub(1);
-                    self.bus.write_byte(self.sp, self.registers.b);
-                    self.sp = self.sp.wrapping_sub(1);
-                    self.bus.write_byte(self.sp, self.registers.c);
+            Instruction::OR(source) => {
+                let value = self.read_arithmetic_source(source);
+                self.registers.a |= value;
+                self.registers.f.zero = self.registers.a == 0;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = false;
+            }
+            Instruction::POP(target) => {
+                let value = self.pop();
+                match target {
+                    instructions::PopTarget::AF => self.registers.set_af(value),
+                    instructions::PopTarget::BC => self.registers.set_bc(value),
+                    instructions::PopTarget::DE => self.registers.set_de(value),
+                    instructions::PopTarget::HL => self.registers.set_hl(value),
                 }
-                _ => {
-                    panic!("Unknown target.");
+            }
+            Instruction::PUSH(target) => {
+                let value = match target {
+                    instructions::PushTarget::AF => self.registers.get_af(),
+                    instructions::PushTarget::BC => self.registers.get_bc(),
+                    instructions::PushTarget::DE => self.registers.get_de(),
+                    instructions::PushTarget::HL => self.registers.get_hl(),
+                };
+                self.internal_cycle();
+                self.push(value);
+            }
+            Instruction::RET(condition) => {
+                // Unlike the other conditional instructions, RET cc spends an extra cycle
+                // evaluating its condition.
+                if !matches!(condition, instructions::JumpCondition::Always) {
+                    self.internal_cycle();
                 }
-            },
-            Instruction::RET => {
-                let least_significant_byte = self.bus.read_byte(self.sp) as u16;
-                self.sp = self.sp.wrapping_add(1);
-                let most_significant_byte = self.bus.read_byte(self.sp) as u16;
-                self.sp = self.sp.wrapping_add(1);
-                self.pc = (most_significant_byte << 8) | least_significant_byte;
-
-                println!("Returning 0x{:x}", self.pc);
-            }
-            Instruction::RL(target) => match target {
-                instructions::RotateTarget::C => {
-                    let highest_bit = self.registers.c & 0x80 != 0;
-                    self.registers.c <<= 1;
-                    if self.flags_register.carry {
-                        self.registers.c |= 0x1;
-                    }
-                    self.flags_register.zero = self.registers.c == 0;
-                    self.flags_register.subtract = false;
-                    self.flags_register.half_carry = false;
-                    self.flags_register.carry = highest_bit;
+                if self.check_jump_condition(condition) {
+                    self.pc = self.pop();
+                    self.internal_cycle();
                 }
-            },
+            }
+            Instruction::RETI => {
+                self.pc = self.pop();
+                self.internal_cycle();
+                self.ime = true;
+            }
+            Instruction::RES(number, source) => {
+                let value = self.read_bit_source(source);
+                self.write_bit_source(source, value & !(1 << number));
+            }
+            Instruction::RL(target) => {
+                let value = self.read_rotate_target(target);
+                let result = value << 1 | self.registers.f.carry as u8;
+                self.write_rotate_target(target, result, value & 0x80 != 0);
+            }
             Instruction::RLA => {
                 let highest_bit = self.registers.a & 0x80 != 0;
                 self.registers.a <<= 1;
-                if self.flags_register.carry {
+                if self.registers.f.carry {
                     self.registers.a |= 0x1;
                 }
-                self.flags_register.zero = false;
-                self.flags_register.subtract = false;
-                self.flags_register.half_carry = false;
-                self.flags_register.carry = highest_bit;
+                self.registers.f.zero = false;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = highest_bit;
+            }
+            Instruction::RLC(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value.rotate_left(1), value & 0x80 != 0);
+            }
+            Instruction::RLCA => {
+                let highest_bit = self.registers.a & 0x80 != 0;
+                self.registers.a = self.registers.a.rotate_left(1);
+                self.registers.f.zero = false;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = highest_bit;
+            }
+            Instruction::RR(target) => {
+                let value = self.read_rotate_target(target);
+                let result = value >> 1 | (self.registers.f.carry as u8) << 7;
+                self.write_rotate_target(target, result, value & 0x1 != 0);
+            }
+            Instruction::RRA => {
+                let lowest_bit = self.registers.a & 0x1 != 0;
+                self.registers.a >>= 1;
+                if self.registers.f.carry {
+                    self.registers.a |= 0x80;
+                }
+                self.registers.f.zero = false;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = lowest_bit;
+            }
+            Instruction::RRC(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value.rotate_right(1), value & 0x1 != 0);
+            }
+            Instruction::RRCA => {
+                let lowest_bit = self.registers.a & 0x1 != 0;
+                self.registers.a = self.registers.a.rotate_right(1);
+                self.registers.f.zero = false;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = lowest_bit;
+            }
+            Instruction::RST(address) => {
+                self.internal_cycle();
+                self.push(self.pc);
+                self.pc = address as u16;
+            }
+            Instruction::SBC(source) => {
+                let value = self.read_arithmetic_source(source);
+                self.sub(value, self.registers.f.carry);
+            }
+            Instruction::SCF => {
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = true;
+            }
+            Instruction::SET(number, source) => {
+                let value = self.read_bit_source(source);
+                self.write_bit_source(source, value | 1 << number);
+            }
+            Instruction::SLA(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value << 1, value & 0x80 != 0);
+            }
+            Instruction::SRA(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value >> 1 | value & 0x80, value & 0x1 != 0);
+            }
+            Instruction::SRL(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value >> 1, value & 0x1 != 0);
+            }
+            Instruction::STOP => {
+                // STOP is followed by a padding byte that is skipped.
+                self.pc = self.pc.wrapping_add(1);
+                // DIV is reset either way.
+                self.bus.write_byte(0xff04, 0);
+                if self.bus.speed_switch_armed() {
+                    self.bus.switch_speed();
+                } else {
+                    self.stopped = true;
+                }
             }
             Instruction::SUB(source) => {
-                let source_value = match source {
-                    instructions::ArithmeticSource::B => self.registers.b,
-                    _ => {
-                        panic!("TODO: implement other sources")
-                    }
-                };
-
-                let (diff, did_overflow) = self.registers.a.overflowing_sub(source_value);
-                self.flags_register.zero = diff == 0;
-                self.flags_register.subtract = true;
-                self.flags_register.carry = did_overflow;
-                self.flags_register.half_carry = (self.registers.a & 0x0F) > (source_value & 0x0F);
-                self.registers.a = diff;
+                let value = self.read_arithmetic_source(source);
+                self.sub(value, false);
+            }
+            Instruction::SWAP(target) => {
+                let value = self.read_rotate_target(target);
+                self.write_rotate_target(target, value.rotate_left(4), false);
             }
             Instruction::XOR(target, source) => {
-                let source_value = match source {
-                    instructions::ArithmeticSource::A => self.registers.a,
-                    _ => {
-                        panic!("TODO: implement other sources")
-                    }
-                };
+                let source_value = self.read_arithmetic_source(source);
                 match target {
                     instructions::ArithmeticTarget::A => self.registers.a ^= source_value,
-                    _ => {
-                        panic!("TODO: implement other targets")
-                    }
                 };
+                self.registers.f.zero = self.registers.a == 0;
+                self.registers.f.subtract = false;
+                self.registers.f.half_carry = false;
+                self.registers.f.carry = false;
             }
-            _ => {}
         }
     }
 
-    pub fn load_bootstrap(&mut self, bootstrap_bin: &[u8]) {
-        self.bus.write_array(0, bootstrap_bin);
+    fn read_byte(&mut self, address: u16) -> u8 {
+        self.advance(4);
+        self.bus.read_byte(address)
     }
 
-    pub fn load_cartridge(&mut self, cartridge_bin: &[u8]) {
-        self.bus.write_array(0, cartridge_bin);
+    fn write_byte(&mut self, address: u16, value: u8) {
+        self.advance(4);
+        self.bus.write_byte(address, value);
+    }
+
+    // An M-cycle in which the CPU does not touch the bus.
+    fn internal_cycle(&mut self) {
+        self.advance(4);
+    }
+
+    fn advance(&mut self, cycles: u32) {
+        self.instruction_cycles += cycles;
+        if let TimingMode::MCycle = self.timing_mode {
+            self.bus.tick(cycles);
+        }
+    }
+
+    fn read_next_byte(&mut self) -> u8 {
+        let value = self.read_byte(self.pc);
+        self.pc = self.pc.wrapping_add(1);
+        value
+    }
+
+    fn read_next_word(&mut self) -> u16 {
+        let least_significant_byte = self.read_next_byte() as u16;
+        let most_significant_byte = self.read_next_byte() as u16;
+        (most_significant_byte << 8) | least_significant_byte
+    }
+
+    fn push(&mut self, value: u16) {
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, (value >> 8) as u8);
+        self.sp = self.sp.wrapping_sub(1);
+        self.write_byte(self.sp, (value & 0xff) as u8);
+    }
+
+    fn pop(&mut self) -> u16 {
+        let least_significant_byte = self.read_byte(self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        let most_significant_byte = self.read_byte(self.sp) as u16;
+        self.sp = self.sp.wrapping_add(1);
+        (most_significant_byte << 8) | least_significant_byte
+    }
+
+    fn read_arithmetic_source(&mut self, source: instructions::ArithmeticSource) -> u8 {
+        match source {
+            instructions::ArithmeticSource::A => self.registers.a,
+            instructions::ArithmeticSource::B => self.registers.b,
+            instructions::ArithmeticSource::C => self.registers.c,
+            instructions::ArithmeticSource::D => self.registers.d,
+            instructions::ArithmeticSource::E => self.registers.e,
+            instructions::ArithmeticSource::H => self.registers.h,
+            instructions::ArithmeticSource::L => self.registers.l,
+            instructions::ArithmeticSource::HL_ => self.read_byte(self.registers.get_hl()),
+            instructions::ArithmeticSource::N8 => self.read_next_byte(),
+        }
+    }
+
+    fn read_rotate_target(&mut self, target: instructions::RotateTarget) -> u8 {
+        match target {
+            instructions::RotateTarget::A => self.registers.a,
+            instructions::RotateTarget::B => self.registers.b,
+            instructions::RotateTarget::C => self.registers.c,
+            instructions::RotateTarget::D => self.registers.d,
+            instructions::RotateTarget::E => self.registers.e,
+            instructions::RotateTarget::H => self.registers.h,
+            instructions::RotateTarget::L => self.registers.l,
+            instructions::RotateTarget::HL_ => self.read_byte(self.registers.get_hl()),
+        }
+    }
+
+    // Stores the result of a CB-prefixed rotate, shift or swap and sets the flags they all share.
+    fn write_rotate_target(&mut self, target: instructions::RotateTarget, value: u8, carry: bool) {
+        match target {
+            instructions::RotateTarget::A => self.registers.a = value,
+            instructions::RotateTarget::B => self.registers.b = value,
+            instructions::RotateTarget::C => self.registers.c = value,
+            instructions::RotateTarget::D => self.registers.d = value,
+            instructions::RotateTarget::E => self.registers.e = value,
+            instructions::RotateTarget::H => self.registers.h = value,
+            instructions::RotateTarget::L => self.registers.l = value,
+            instructions::RotateTarget::HL_ => self.write_byte(self.registers.get_hl(), value),
+        }
+        self.registers.f.zero = value == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = false;
+        self.registers.f.carry = carry;
+    }
+
+    fn read_bit_source(&mut self, source: instructions::BitSource) -> u8 {
+        match source {
+            instructions::BitSource::A => self.registers.a,
+            instructions::BitSource::B => self.registers.b,
+            instructions::BitSource::C => self.registers.c,
+            instructions::BitSource::D => self.registers.d,
+            instructions::BitSource::E => self.registers.e,
+            instructions::BitSource::H => self.registers.h,
+            instructions::BitSource::L => self.registers.l,
+            instructions::BitSource::HL_ => self.read_byte(self.registers.get_hl()),
+        }
+    }
+
+    fn write_bit_source(&mut self, source: instructions::BitSource, value: u8) {
+        match source {
+            instructions::BitSource::A => self.registers.a = value,
+            instructions::BitSource::B => self.registers.b = value,
+            instructions::BitSource::C => self.registers.c = value,
+            instructions::BitSource::D => self.registers.d = value,
+            instructions::BitSource::E => self.registers.e = value,
+            instructions::BitSource::H => self.registers.h = value,
+            instructions::BitSource::L => self.registers.l = value,
+            instructions::BitSource::HL_ => self.write_byte(self.registers.get_hl(), value),
+        }
+    }
+
+    fn check_jump_condition(&self, condition: instructions::JumpCondition) -> bool {
+        match condition {
+            instructions::JumpCondition::Always => true,
+            instructions::JumpCondition::NZ => !self.registers.f.zero,
+            instructions::JumpCondition::Z => self.registers.f.zero,
+            instructions::JumpCondition::NC => !self.registers.f.carry,
+            instructions::JumpCondition::C => self.registers.f.carry,
+        }
+    }
+
+    fn add(&mut self, value: u8, carry: bool) {
+        let carry = carry as u8;
+        let sum = self.registers.a as u16 + value as u16 + carry as u16;
+        self.registers.f.zero = sum & 0xff == 0;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = (self.registers.a & 0x0f) + (value & 0x0f) + carry > 0xf;
+        self.registers.f.carry = sum > 0xff;
+        self.registers.a = sum as u8;
+    }
+
+    fn sub(&mut self, value: u8, carry: bool) {
+        let carry = carry as u8;
+        let diff = self.registers.a.wrapping_sub(value).wrapping_sub(carry);
+        self.registers.f.zero = diff == 0;
+        self.registers.f.subtract = true;
+        self.registers.f.half_carry = (self.registers.a & 0x0f) < (value & 0x0f) + carry;
+        self.registers.f.carry = (self.registers.a as u16) < value as u16 + carry as u16;
+        self.registers.a = diff;
+    }
+
+    // Shared by ADD SP, e8 and LD HL, SP + e8. The flags come from the unsigned addition of the
+    // low byte of SP and the offset.
+    fn add_sp_offset(&mut self, offset: i8) -> u16 {
+        let unsigned_offset = offset as u8 as u16;
+        self.registers.f.zero = false;
+        self.registers.f.subtract = false;
+        self.registers.f.half_carry = (self.sp & 0xf) + (unsigned_offset & 0xf) > 0xf;
+        self.registers.f.carry = (self.sp & 0xff) + unsigned_offset > 0xff;
+        self.sp.wrapping_add_signed(offset.into())
+    }
+
+    // Maps the boot ROM over the cartridge, so execution starts in it at 0x0000.
+    pub fn load_bootstrap(&mut self, bootstrap_bin: &[u8]) -> Result<(), EmuError> {
+        self.bus.load_boot_rom(BootRom::new(bootstrap_bin)?);
+        Ok(())
+    }
+
+    pub fn load_bootstrap_file(&mut self, path: &str) -> Result<(), EmuError> {
+        self.bus.load_boot_rom(BootRom::from_file(path)?);
+        Ok(())
+    }
+
+    pub fn load_cartridge(&mut self, cartridge_bin: &[u8]) -> Result<(), EmuError> {
+        let (cartridge, header) = cartridge::from_rom(cartridge_bin)?;
+        self.bus.load_cartridge(cartridge, header);
+        Ok(())
+    }
+
+    // Plugs in a cartridge built outside of `load_cartridge`, e.g. a custom mapper. It has no
+    // header as far as the emulator is concerned.
+    pub fn insert_cartridge(&mut self, cartridge: Box<dyn Cartridge>) {
+        self.bus.load_cartridge(cartridge, None);
+    }
+
+    pub fn cartridge_header(&self) -> Option<&CartridgeHeader> {
+        self.bus.cartridge_header()
+    }
+
+    // Gives access to the cartridge's own state, e.g. to save or restore it.
+    pub fn cartridge(&self) -> &dyn Cartridge {
+        self.bus.cartridge()
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut dyn Cartridge {
+        self.bus.cartridge_mut()
+    }
+
+    // Loads battery RAM from the save file if there is one, and keeps writing it back there.
+    // Does nothing for cartridges without a battery.
+    pub fn load_save_file(&mut self, save_file: SaveFile) -> Result<(), EmuError> {
+        if self.bus.cartridge().save_data().is_none() {
+            return Ok(());
+        }
+        if let Some(data) = save_file.read()? {
+            self.bus.cartridge_mut().load_save_data(&data);
+        }
+        self.save_file = Some(save_file);
+        self.save_cycles = 0;
+        Ok(())
+    }
+
+    // Writes battery RAM to the save file, e.g. before exiting.
+    pub fn flush_save(&mut self) -> Result<(), EmuError> {
+        if let (Some(save_file), Some(data)) = (&self.save_file, self.bus.cartridge().save_data()) {
+            save_file.write(&data)?;
+            self.bus.mark_saved();
+        }
+        self.save_cycles = 0;
+        Ok(())
+    }
+
+    // The error from the last time `step` failed to flush battery RAM, if any. Worth checking
+    // every so often, since `step` carries on regardless.
+    pub fn take_save_error(&mut self) -> Option<EmuError> {
+        self.save_error.take()
+    }
+
+    // Picks what drives the cartridge clock, for this and any later cartridge.
+    pub fn set_rtc_mode(&mut self, mode: RtcMode) {
+        self.bus.set_rtc_mode(mode);
+    }
+
+    // Tilts the cartridge's accelerometer, in g on each axis. Keyboard, mouse or scripted input
+    // can all be mapped onto this.
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.bus.set_tilt(x, y);
+    }
+
+    // Lets the frontend forward the rumble motor state to a controller, or just log it.
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.bus.set_rumble_callback(callback);
+    }
+
+    // Lets the frontend play or log the cartridge speaker's tone.
+    pub fn set_tone_callback(&mut self, callback: ToneCallback) {
+        self.bus.set_tone_callback(callback);
+    }
+
+    // Sets what the Game Boy Camera's sensor sees in the cartridge currently loaded, e.g. a
+    // `StillImage` from a file or a closure returning frames.
+    pub fn set_camera_image_source(&mut self, image_source: Box<dyn ImageSource>) {
+        self.bus.cartridge_mut().set_image_source(image_source);
+    }
+
+    // Saves the photos stored on a Game Boy Camera cartridge as PNG files in `directory`.
+    pub fn export_camera_photos(&self, directory: &str) -> Result<usize, EmuError> {
+        self.bus.cartridge().export_photos(directory)
+    }
+
+    // Plugs in the infrared device used by the CGB port and by cartridges with an IR port, e.g.
+    // one end of `infrared::link` to talk to another instance.
+    pub fn set_infrared(&mut self, infrared: Rc<dyn Infrared>) {
+        self.bus.set_infrared(infrared);
+    }
+
+    // Presses or releases a key. Requests the joypad interrupt when the game has selected its
+    // group in P1, and wakes the CPU from STOP.
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        self.bus.set_button(button, pressed);
+    }
+
+    // Starts at the cartridge entry point with the registers and I/O state the boot ROM of the
+    // given model leaves behind. Needs the cartridge to be loaded, because some of that state
+    // depends on its header.
+    pub fn skip_boot_rom(&mut self, model: Model) {
+        let header_checksum = self.bus.read_byte(0x014d);
+        let cgb_flag = self.bus.read_byte(0x0143);
+
+        match model {
+            Model::DMG0 => {
+                self.registers.set_af(0x0100);
+                self.registers.set_bc(0xff13);
+                self.registers.set_de(0x00c1);
+                self.registers.set_hl(0x8403);
+            }
+            Model::DMG | Model::MGB => {
+                let a = if model == Model::MGB { 0xff } else { 0x01 };
+                // H and C are left over from the header checksum loop.
+                let f = if header_checksum == 0 { 0x80 } else { 0xb0 };
+                self.registers.set_af((a as u16) << 8 | f);
+                self.registers.set_bc(0x0013);
+                self.registers.set_de(0x00d8);
+                self.registers.set_hl(0x014d);
+            }
+            Model::SGB | Model::SGB2 => {
+                let a = if model == Model::SGB2 { 0xff } else { 0x01 };
+                self.registers.set_af((a as u16) << 8);
+                self.registers.set_bc(0x0014);
+                self.registers.set_de(0x0000);
+                self.registers.set_hl(0xc060);
+            }
+            Model::CGB | Model::AGB => {
+                self.registers.set_af(0x1180);
+                if cgb_flag & 0x80 != 0 {
+                    self.registers.set_bc(0x0000);
+                    self.registers.set_de(0xff56);
+                    self.registers.set_hl(0x000d);
+                } else {
+                    // In DMG compatibility mode B holds the title checksum the boot ROM uses to
+                    // pick a palette, for Nintendo titles only.
+                    let b = self.title_checksum();
+                    self.registers.set_bc((b as u16) << 8);
+                    self.registers.set_de(0x0008);
+                    self.registers.set_hl(if b == 0x43 || b == 0x58 {
+                        0x991a
+                    } else {
+                        0x007c
+                    });
+                }
+                if model == Model::AGB {
+                    // The AGB boot ROM ends with an extra INC B.
+                    self.execute(Instruction::INC(instructions::IncTarget::B));
+                }
+            }
+        }
+        self.sp = 0xfffe;
+        self.pc = 0x0100;
+
+        self.bus
+            .set_cgb_mode(model.is_cgb() && cgb_flag & 0x80 != 0);
+        for (address, value) in POST_BOOT_IO_REGISTERS {
+            self.bus.set_io_register(address, value);
+        }
+        if model.is_cgb() {
+            self.bus.set_io_register(0xff02, 0x7f);
+            self.bus.set_io_register(0xff46, 0x00);
+        }
+        if model.is_sgb() {
+            self.bus.set_io_register(0xff26, 0xf0);
+        }
+        self.bus.set_div_counter(model.post_boot_div_counter());
+        self.bus.write_byte(0xff50, 0x01);
+    }
+
+    // Sum of the title bytes if the header names Nintendo as the licensee, otherwise zero.
+    fn title_checksum(&self) -> u8 {
+        let old_licensee = self.bus.read_byte(0x014b);
+        let new_licensee = [self.bus.read_byte(0x0144), self.bus.read_byte(0x0145)];
+        if old_licensee == 0x01 || (old_licensee == 0x33 && new_licensee == *b"01") {
+            (0x0134..=0x0143).fold(0u8, |sum, address| {
+                sum.wrapping_add(self.bus.read_byte(address))
+            })
+        } else {
+            0x00
+        }
     }
 }
 
+// I/O registers as the DMG boot ROM leaves them. Other models differ in a few of them.
+const POST_BOOT_IO_REGISTERS: [(u16, u8); 32] = [
+    (0xff00, 0xcf),
+    (0xff01, 0x00),
+    (0xff02, 0x7e),
+    (0xff05, 0x00),
+    (0xff06, 0x00),
+    (0xff07, 0xf8),
+    (0xff0f, 0xe1),
+    (0xff10, 0x80),
+    (0xff11, 0xbf),
+    (0xff12, 0xf3),
+    (0xff13, 0xff),
+    (0xff14, 0xbf),
+    (0xff16, 0x3f),
+    (0xff17, 0x00),
+    (0xff18, 0xff),
+    (0xff19, 0xbf),
+    (0xff1a, 0x7f),
+    (0xff1b, 0xff),
+    (0xff1c, 0x9f),
+    (0xff1d, 0xff),
+    (0xff1e, 0xbf),
+    (0xff20, 0xff),
+    (0xff21, 0x00),
+    (0xff22, 0x00),
+    (0xff23, 0xbf),
+    (0xff24, 0x77),
+    (0xff25, 0xf3),
+    (0xff26, 0xf1),
+    (0xff40, 0x91),
+    (0xff41, 0x85),
+    (0xff46, 0xff),
+    (0xff47, 0xfc),
+];
+
 impl Default for CPU {
     fn default() -> Self {
         Self {
             registers: Registers::default(),
-            flags_register: FlagsRegister::default(),
             sp: 0,
             pc: 0,
             bus: MemoryBus::default(),
+            ime: false,
+            ime_scheduled: false,
+            halted: false,
+            stopped: false,
+            halt_bug: false,
+            locked: false,
+
+            illegal_opcode_policy: IllegalOpcodePolicy::LockUp,
+
+            timing_mode: TimingMode::Instruction,
+            instruction_cycles: 0,
+
+            save_file: None,
+            save_cycles: 0,
+            save_error: None,
+        }
+    }
+}
 
-            cycles: 0,
-            last_ly_update: 0,
+// Writes out unsaved changes to battery RAM. Errors can't be reported from here, so call
+// `flush_save` before dropping the CPU to see them.
+impl Drop for CPU {
+    fn drop(&mut self) {
+        if self.save_file.is_some() && self.bus.save_dirty() {
+            let _ = self.flush_save();
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::flag_registers::FlagsRegister;
+    use super::*;
+
+    // (A, F) before and after DAA, as SameBoy computes them, covering every adjustment with and
+    // without the flags that force it.
+    const DAA_VECTORS: [((u8, u8), (u8, u8)); 20] = [
+        ((0x00, 0x00), (0x00, 0x80)),
+        ((0x09, 0x00), (0x09, 0x00)),
+        ((0x0a, 0x00), (0x10, 0x00)),
+        ((0x3c, 0x00), (0x42, 0x00)),
+        ((0x15, 0x20), (0x1b, 0x00)),
+        ((0x99, 0x00), (0x99, 0x00)),
+        ((0x9a, 0x00), (0x00, 0x90)),
+        ((0x9f, 0x00), (0x05, 0x10)),
+        ((0xa0, 0x00), (0x00, 0x90)),
+        ((0xff, 0x00), (0x65, 0x10)),
+        ((0x45, 0x10), (0xa5, 0x10)),
+        ((0x00, 0x30), (0x66, 0x10)),
+        ((0x0f, 0x40), (0x0f, 0x40)),
+        ((0x00, 0x60), (0xfa, 0x40)),
+        ((0x03, 0x60), (0xfd, 0x40)),
+        ((0x06, 0x60), (0x00, 0xc0)),
+        ((0x00, 0x50), (0xa0, 0x50)),
+        ((0x60, 0x50), (0x00, 0xd0)),
+        ((0x00, 0x70), (0x9a, 0x50)),
+        ((0x66, 0x70), (0x00, 0xd0)),
+    ];
+
+    #[test]
+    fn daa_matches_known_results() {
+        for ((a, f), expected) in DAA_VECTORS {
+            let mut cpu = CPU::default();
+            cpu.registers.a = a;
+            cpu.registers.f = FlagsRegister::from(f);
+            cpu.execute(Instruction::DAA);
+
+            assert_eq!(
+                (cpu.registers.a, u8::from(cpu.registers.f)),
+                expected,
+                "DAA with A=0x{:02x} F=0x{:02x}",
+                a,
+                f
+            );
+        }
+    }
+
+    // Independent formulation of DAA, the one SameBoy uses, that adjusts the low digit before
+    // looking at the high one and detects the carry out of the 9-bit result.
+    fn reference_daa(a: u8, f: u8) -> (u8, u8) {
+        let flags = FlagsRegister::from(f);
+        let mut result = a as u16;
+        let mut carry = flags.carry;
+        if flags.subtract {
+            if flags.half_carry {
+                result = result.wrapping_sub(0x06) & 0xff;
+            }
+            if flags.carry {
+                result = result.wrapping_sub(0x60);
+            }
+        } else {
+            if flags.half_carry || result & 0xf > 0x9 {
+                result += 0x06;
+            }
+            if flags.carry || result > 0x9f {
+                result += 0x60;
+            }
+            carry |= result & 0x100 != 0;
+        }
+
+        let result = (result & 0xff) as u8;
+        let flags = FlagsRegister {
+            zero: result == 0,
+            subtract: flags.subtract,
+            half_carry: false,
+            carry,
+        };
+        (result, u8::from(flags))
+    }
+
+    #[test]
+    fn daa_matches_reference_for_every_input() {
+        for a in 0..=0xffu8 {
+            for f in (0..=0xf0u8).step_by(0x10) {
+                let mut cpu = CPU::default();
+                cpu.registers.a = a;
+                cpu.registers.f = FlagsRegister::from(f);
+                cpu.execute(Instruction::DAA);
+
+                assert_eq!(
+                    (cpu.registers.a, u8::from(cpu.registers.f)),
+                    reference_daa(a, f),
+                    "DAA with A=0x{:02x} F=0x{:02x}",
+                    a,
+                    f
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn stop_waits_for_a_selected_key() {
+        let mut cpu = CPU::default();
+        cpu.bus.set_io_register(0xff0f, Interrupt::Joypad.mask());
+        cpu.bus.write_byte(0xff00, 0x20);
+        cpu.execute(Instruction::STOP);
+        let pc = cpu.pc;
+
+        // A stale joypad interrupt doesn't wake it, and neither does a key that isn't selected.
+        cpu.set_button(Button::A, true);
+        assert_eq!(cpu.step().unwrap(), CYCLES_PER_LINE);
+        assert!(cpu.stopped);
+        assert_eq!(cpu.pc, pc);
+        assert_eq!(cpu.bus.read_byte(0xff44), 1);
+
+        cpu.set_button(Button::Down, true);
+        cpu.step().unwrap();
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.bus.read_byte(0xff00), 0xe7);
+    }
+
+    #[test]
+    fn stop_switches_speed_when_key1_is_armed() {
+        // STOP with its padding byte, then NOP.
+        let mut cpu = cpu_running(&[0x10, 0x00, 0x00]);
+        cpu.bus.set_cgb_mode(true);
+        cpu.bus.write_byte(0xff4d, 0x01);
+        assert_eq!(cpu.bus.read_byte(0xff4d), 0x7f);
+
+        cpu.step().unwrap();
+        assert!(!cpu.stopped);
+        assert_eq!(cpu.bus.read_byte(0xff4d), 0xfe);
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert_eq!(cpu.pc, 0xc003);
+
+        // Unarmed, STOP stops instead of switching back.
+        cpu.pc = 0xc000;
+        cpu.step().unwrap();
+        assert!(cpu.stopped);
+        assert_eq!(cpu.bus.read_byte(0xff4d), 0xfe);
+    }
+
+    // A 32 KiB ROM-only cartridge with 8 KiB of battery RAM.
+    fn battery_rom() -> Vec<u8> {
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x09;
+        rom[0x149] = 0x02;
+        rom
+    }
+
+    #[test]
+    fn only_changes_to_save_data_make_it_dirty() {
+        let mut cpu = CPU::default();
+        let camera = cartridge::pocket_camera::PocketCamera::new(vec![0; 0x8000], 0x20000, true);
+        cpu.insert_cartridge(Box::new(camera));
+
+        // Camera registers, then RAM with writes disabled.
+        cpu.bus.write_byte(0x4000, 0x10);
+        cpu.bus.write_byte(0xa001, 0x12);
+        cpu.bus.write_byte(0x4000, 0x00);
+        cpu.bus.write_byte(0xa000, 0x34);
+        assert!(!cpu.bus.save_dirty());
+
+        cpu.bus.write_byte(0x0000, 0x0a);
+        cpu.bus.write_byte(0xa000, 0x34);
+        assert!(cpu.bus.save_dirty());
+    }
+
+    #[test]
+    fn dropping_the_cpu_flushes_the_save() {
+        let directory = std::env::temp_dir().join(format!("gb-emulator-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("drop.sav");
+        let _ = std::fs::remove_file(&path);
+
+        let mut cpu = CPU::default();
+        cpu.load_cartridge(&battery_rom()).unwrap();
+        cpu.load_save_file(SaveFile::new(path.to_str().unwrap()))
+            .unwrap();
+        cpu.bus.write_byte(0xa000, 0x56);
+        drop(cpu);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(data[0], 0x56);
+    }
+
+    #[test]
+    fn failed_flushes_keep_the_cycles_and_the_error() {
+        let directory = std::env::temp_dir().join(format!("gb-emulator-{}", std::process::id()));
+        let path = directory.join("missing").join("step.sav");
+
+        let mut cpu = CPU::default();
+        cpu.load_cartridge(&battery_rom()).unwrap();
+        cpu.load_save_file(SaveFile::new(path.to_str().unwrap()))
+            .unwrap();
+        cpu.bus.write_byte(0xa000, 0x56);
+        cpu.pc = 0xc000;
+        cpu.save_cycles = SAVE_FLUSH_CYCLES - 4;
+
+        assert_eq!(cpu.step().unwrap(), 4);
+        assert!(matches!(cpu.take_save_error(), Some(EmuError::Io { .. })));
+        assert!(cpu.take_save_error().is_none());
+        assert_eq!(cpu.save_cycles, 0);
+    }
+
+    #[test]
+    fn pop_af_clears_lower_nibble_of_f() {
+        let mut cpu = CPU::default();
+        cpu.sp = 0xd000;
+        cpu.push(0x12ff);
+        cpu.execute(Instruction::POP(instructions::PopTarget::AF));
+
+        assert_eq!(cpu.registers.get_af(), 0x12f0);
+    }
+
+    // A CPU about to run the given code from WRAM, with every interrupt enabled in IE.
+    fn cpu_running(code: &[u8]) -> CPU {
+        let mut cpu = CPU::default();
+        for (i, byte) in code.iter().enumerate() {
+            cpu.bus.write_byte(0xc000 + i as u16, *byte);
+        }
+        cpu.pc = 0xc000;
+        cpu.sp = 0xd000;
+        cpu.bus.write_byte(0xffff, 0x1f);
+        cpu
+    }
+
+    // M-cycles per unprefixed opcode, with conditional branches not taken, as measured on hardware
+    // by blargg's instr_timing test. 0 marks opcodes that aren't timed this way: STOP, HALT, the
+    // CB prefix and the illegal opcodes.
+    #[rustfmt::skip]
+    const OPCODE_M_CYCLES: [u8; 256] = [
+        1, 3, 2, 2, 1, 1, 2, 1, 5, 2, 2, 2, 1, 1, 2, 1,
+        0, 3, 2, 2, 1, 1, 2, 1, 3, 2, 2, 2, 1, 1, 2, 1,
+        2, 3, 2, 2, 1, 1, 2, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+        2, 3, 2, 2, 3, 3, 3, 1, 2, 2, 2, 2, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 2, 2, 2, 2, 2, 0, 2, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        1, 1, 1, 1, 1, 1, 2, 1, 1, 1, 1, 1, 1, 1, 2, 1,
+        2, 3, 3, 4, 3, 4, 2, 4, 2, 4, 3, 0, 3, 6, 2, 4,
+        2, 3, 3, 0, 3, 4, 2, 4, 2, 4, 3, 0, 3, 0, 2, 4,
+        3, 3, 2, 0, 0, 4, 2, 4, 4, 1, 4, 0, 0, 0, 2, 4,
+        3, 3, 2, 1, 0, 4, 2, 4, 3, 2, 4, 1, 0, 0, 2, 4,
+    ];
+
+    // Same, for branches that are taken.
+    fn taken_m_cycles(opcode: u8) -> Option<u8> {
+        match opcode {
+            0x20 | 0x28 | 0x30 | 0x38 => Some(3),
+            0xc0 | 0xc8 | 0xd0 | 0xd8 => Some(5),
+            0xc2 | 0xca | 0xd2 | 0xda => Some(4),
+            0xc4 | 0xcc | 0xd4 | 0xdc => Some(6),
+            _ => None,
+        }
+    }
+
+    // M-cycles of a CB-prefixed opcode, including the prefix.
+    fn cb_m_cycles(opcode: u8) -> u8 {
+        match opcode {
+            // BIT n,(HL) only reads.
+            0x40..=0x7f if opcode & 0x07 == 0x06 => 3,
+            _ if opcode & 0x07 == 0x06 => 4,
+            _ => 2,
+        }
+    }
+
+    // Runs one instruction from WRAM, with operands that point back into RAM, and returns its
+    // T-cycles.
+    fn instruction_cycles(code: &[u8], flags: u8, timing_mode: TimingMode) -> u32 {
+        let mut cpu = cpu_running(code);
+        cpu.bus.write_byte(0xffff, 0x00);
+        cpu.registers.f = FlagsRegister::from(flags);
+        cpu.registers.set_hl(0xc100);
+        cpu.set_timing_mode(timing_mode);
+        cpu.step().unwrap()
+    }
+
+    #[test]
+    fn instructions_take_the_hardware_cycle_counts() {
+        for opcode in 0..=0xffu8 {
+            let m_cycles = OPCODE_M_CYCLES[opcode as usize];
+            if m_cycles == 0 {
+                continue;
+            }
+            for flags in [0x00, 0xf0] {
+                // Conditions are NZ, Z, NC and C, from bits 3-4 of the opcode.
+                let flag = if opcode & 0x10 == 0 { 0x80 } else { 0x10 };
+                let taken = (flags & flag != 0) == (opcode & 0x08 != 0);
+                let expected = match taken_m_cycles(opcode) {
+                    Some(taken_m_cycles) if taken => taken_m_cycles,
+                    _ => m_cycles,
+                } as u32
+                    * 4;
+                for timing_mode in [TimingMode::Instruction, TimingMode::MCycle] {
+                    let cycles = instruction_cycles(&[opcode, 0x80, 0xc1], flags, timing_mode);
+                    assert_eq!(
+                        cycles, expected,
+                        "opcode 0x{:02x}, F=0x{:02x}",
+                        opcode, flags
+                    );
+                }
+            }
+        }
+
+        for opcode in 0..=0xffu8 {
+            let cycles = instruction_cycles(&[0xcb, opcode], 0x00, TimingMode::Instruction);
+            assert_eq!(
+                cycles,
+                cb_m_cycles(opcode) as u32 * 4,
+                "opcode 0xcb{:02x}",
+                opcode
+            );
+        }
+    }
+
+    // Reads TIMA with LD A,(0xFF05) while it counts every 16 T-cycles. The read is the fourth
+    // M-cycle, so only per M-cycle timing lets the timer move before it.
+    fn tima_read_mid_instruction(timing_mode: TimingMode) -> u8 {
+        let mut cpu = cpu_running(&[0xfa, 0x05, 0xff]);
+        cpu.bus.write_byte(0xff07, 0x05);
+        cpu.bus.write_byte(0xff04, 0x00);
+        cpu.set_timing_mode(timing_mode);
+        cpu.step().unwrap();
+        cpu.registers.a
+    }
+
+    #[test]
+    fn m_cycle_timing_ticks_the_bus_between_accesses() {
+        assert_eq!(tima_read_mid_instruction(TimingMode::Instruction), 0);
+        assert_eq!(tima_read_mid_instruction(TimingMode::MCycle), 1);
+    }
+
+    #[test]
+    fn interrupt_dispatch_takes_5_m_cycles() {
+        let mut cpu = cpu_running(&[0x00]);
+        cpu.ime = true;
+        cpu.bus.set_io_register(0xff0f, Interrupt::Timer.mask());
+
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.pc, 0x50);
+        assert_eq!(cpu.sp, 0xcffe);
+        assert_eq!(cpu.pop(), 0xc000);
+        assert!(!cpu.ime);
+        assert_eq!(cpu.bus.pending_interrupts(), 0);
+    }
+
+    #[test]
+    fn interrupts_are_dispatched_in_priority_order() {
+        let mut cpu = cpu_running(&[0x00]);
+        cpu.bus.set_io_register(0xff0f, 0x1f);
+        for handler in [0x40, 0x48, 0x50, 0x58, 0x60] {
+            cpu.ime = true;
+            cpu.step().unwrap();
+            assert_eq!(cpu.pc, handler);
+        }
+        assert_eq!(cpu.bus.pending_interrupts(), 0);
+    }
+
+    #[test]
+    fn ei_takes_effect_after_the_next_instruction() {
+        // EI, NOP, NOP
+        let mut cpu = cpu_running(&[0xfb, 0x00, 0x00]);
+        cpu.bus.set_io_register(0xff0f, Interrupt::VBlank.mask());
+
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0xc001);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0xc002);
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+        assert_eq!(cpu.pop(), 0xc002);
+    }
+
+    #[test]
+    fn di_right_after_ei_keeps_interrupts_off() {
+        // EI, DI, NOP
+        let mut cpu = cpu_running(&[0xfb, 0xf3, 0x00]);
+        cpu.bus.set_io_register(0xff0f, Interrupt::VBlank.mask());
+        for _ in 0..3 {
+            cpu.step().unwrap();
+        }
+        assert_eq!(cpu.pc, 0xc003);
+        assert!(!cpu.ime);
+    }
+
+    #[test]
+    fn pushing_pc_onto_ie_can_cancel_the_dispatch() {
+        // The high byte of PC, 0xC0, lands on IE and disables VBlank before it is chosen.
+        let mut cpu = cpu_running(&[0x00]);
+        cpu.bus.write_byte(0xffff, Interrupt::VBlank.mask());
+        cpu.bus.set_io_register(0xff0f, Interrupt::VBlank.mask());
+        cpu.ime = true;
+        cpu.sp = 0x0000;
+        assert_eq!(cpu.step().unwrap(), 20);
+        assert_eq!(cpu.pc, 0x0000);
+        assert_eq!(cpu.bus.read_byte(0xffff), 0xc0);
+        // Still requested, as it was never serviced.
+        assert_eq!(cpu.bus.read_byte(0xff0f) & 0x1f, Interrupt::VBlank.mask());
+
+        // With 0xC1 the VBlank bit survives the push.
+        let mut cpu = cpu_running(&[0x00]);
+        cpu.pc = 0xc100;
+        cpu.bus.write_byte(0xffff, Interrupt::VBlank.mask());
+        cpu.bus.set_io_register(0xff0f, Interrupt::VBlank.mask());
+        cpu.ime = true;
+        cpu.sp = 0x0000;
+        cpu.step().unwrap();
+        assert_eq!(cpu.pc, 0x40);
+    }
+}
@@ -1,3 +1,4 @@
+#[derive(Clone, Copy, Default)]
 pub struct FlagsRegister {
     pub zero: bool,
     pub subtract: bool,
@@ -34,14 +35,3 @@ impl std::convert::From<u8> for FlagsRegister {
         }
     }
 }
-
-impl Default for FlagsRegister {
-    fn default() -> Self {
-        Self {
-            zero: false,
-            subtract: false,
-            half_carry: false,
-            carry: false,
-        }
-    }
-}
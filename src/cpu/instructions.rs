@@ -1,35 +1,84 @@
 pub enum Instruction {
+    ADC(ArithmeticSource),
     ADD(ArithmeticSource),
+    ADDHL(AddHLSource),
+    ADDSP,
+    AND(ArithmeticSource),
     BIT(u8, BitSource),
-    CALL,
+    CALL(JumpCondition),
+    CCF,
     CP(CompareSource),
+    CPL,
+    DAA,
     DEC(DecrementTarget),
+    DI,
+    EI,
+    HALT,
     INC(IncTarget),
+    JP(JumpCondition),
+    JPHL,
     JR(JumpCondition),
     LD(LoadTarget, LoadSource),
     LDH(LoadHTarget, LoadHSource),
+    // LD HL, SP + e8
+    LDHLSP,
     LDN16(LoadTypeN16),
+    // LD [n16], SP
+    LDN16SP,
+    LDSPHL,
     NOP,
+    OR(ArithmeticSource),
     POP(PopTarget),
     PUSH(PushTarget),
-    RET,
+    RET(JumpCondition),
+    RETI,
+    RES(u8, BitSource),
     RL(RotateTarget),
     RLA,
+    RLC(RotateTarget),
+    RLCA,
+    RR(RotateTarget),
+    RRA,
+    RRC(RotateTarget),
+    RRCA,
+    RST(u8),
+    SBC(ArithmeticSource),
+    SCF,
+    SET(u8, BitSource),
+    SLA(RotateTarget),
+    SRA(RotateTarget),
+    SRL(RotateTarget),
+    STOP,
     SUB(ArithmeticSource),
+    SWAP(RotateTarget),
     XOR(ArithmeticTarget, ArithmeticSource),
 }
 
+pub enum AddHLSource {
+    BC,
+    DE,
+    HL,
+    SP,
+}
+
 pub enum ArithmeticTarget {
     A,
+}
+
+pub enum ArithmeticSource {
+    A,
     B,
     C,
     D,
     E,
     H,
     L,
+    HL_,
+    N8,
 }
 
-pub enum ArithmeticSource {
+#[derive(Clone, Copy)]
+pub enum BitSource {
     A,
     B,
     C,
@@ -38,14 +87,16 @@ pub enum ArithmeticSource {
     H,
     L,
     HL_,
-    HLI,
-}
-
-pub enum BitSource {
-    H,
 }
 
 pub enum CompareSource {
+    A,
+    B,
+    C,
+    D,
+    E,
+    H,
+    L,
     HL_,
     N8,
 }
@@ -56,20 +107,36 @@ pub enum DecrementTarget {
     C,
     D,
     E,
+    H,
+    L,
+    HL_,
+    BC,
+    DE,
+    HL,
+    SP,
 }
 
 pub enum IncTarget {
+    A,
     B,
     C,
+    D,
+    E,
     H,
+    L,
+    HL_,
+    BC,
     DE,
     HL,
+    SP,
 }
 
 pub enum JumpCondition {
     Always,
     NZ,
     Z,
+    NC,
+    C,
 }
 
 pub enum LoadTarget {
@@ -80,6 +147,8 @@ pub enum LoadTarget {
     E,
     H,
     L,
+    BC_,
+    DE_,
     HL_,
     HLD,
     HLI,
@@ -89,11 +158,18 @@ pub enum LoadTarget {
 pub enum LoadSource {
     A,
     B,
+    C,
+    D,
     E,
     H,
     L,
+    BC_,
     DE_,
+    HL_,
+    HLD,
+    HLI,
     N8,
+    N16_,
 }
 
 pub enum LoadHTarget {
@@ -104,25 +180,41 @@ pub enum LoadHTarget {
 
 pub enum LoadHSource {
     A,
+    C_,
     N8_,
 }
 
 pub enum LoadTypeN16 {
+    BC,
     DE,
-    SP,
     HL,
+    SP,
 }
 
+#[derive(Clone, Copy)]
 pub enum RotateTarget {
+    A,
+    B,
     C,
+    D,
+    E,
+    H,
+    L,
+    HL_,
 }
 
 pub enum PopTarget {
+    AF,
     BC,
+    DE,
+    HL,
 }
 
 pub enum PushTarget {
+    AF,
     BC,
+    DE,
+    HL,
 }
 
 impl Instruction {
@@ -134,73 +226,294 @@ impl Instruction {
         }
     }
 
+    // The CB page is fully regular: bits 0-2 select the operand and bits 3-5 select either the
+    // rotate/shift operation or the bit number.
     fn from_byte_prefixed(byte: u8) -> Option<Instruction> {
+        let bit = (byte >> 3) & 0x7;
+        let target = match byte & 0x7 {
+            0x0 => RotateTarget::B,
+            0x1 => RotateTarget::C,
+            0x2 => RotateTarget::D,
+            0x3 => RotateTarget::E,
+            0x4 => RotateTarget::H,
+            0x5 => RotateTarget::L,
+            0x6 => RotateTarget::HL_,
+            _ => RotateTarget::A,
+        };
+        let source = match byte & 0x7 {
+            0x0 => BitSource::B,
+            0x1 => BitSource::C,
+            0x2 => BitSource::D,
+            0x3 => BitSource::E,
+            0x4 => BitSource::H,
+            0x5 => BitSource::L,
+            0x6 => BitSource::HL_,
+            _ => BitSource::A,
+        };
         match byte {
-            0x11 => Some(Instruction::RL(RotateTarget::C)),
-            0x7c => Some(Instruction::BIT(7, BitSource::H)),
-            _ =>
-            /* TODO: Add mapping for rest of instructions */
-            {
-                None
-            }
+            0x00..=0x07 => Some(Instruction::RLC(target)),
+            0x08..=0x0f => Some(Instruction::RRC(target)),
+            0x10..=0x17 => Some(Instruction::RL(target)),
+            0x18..=0x1f => Some(Instruction::RR(target)),
+            0x20..=0x27 => Some(Instruction::SLA(target)),
+            0x28..=0x2f => Some(Instruction::SRA(target)),
+            0x30..=0x37 => Some(Instruction::SWAP(target)),
+            0x38..=0x3f => Some(Instruction::SRL(target)),
+            0x40..=0x7f => Some(Instruction::BIT(bit, source)),
+            0x80..=0xbf => Some(Instruction::RES(bit, source)),
+            0xc0..=0xff => Some(Instruction::SET(bit, source)),
         }
     }
 
     fn from_byte_not_prefixed(byte: u8) -> Option<Instruction> {
         match byte {
             0x0 => Some(Instruction::NOP),
+            0x1 => Some(Instruction::LDN16(LoadTypeN16::BC)),
+            0x2 => Some(Instruction::LD(LoadTarget::BC_, LoadSource::A)),
+            0x3 => Some(Instruction::INC(IncTarget::BC)),
             0x4 => Some(Instruction::INC(IncTarget::B)),
             0x5 => Some(Instruction::DEC(DecrementTarget::B)),
             0x6 => Some(Instruction::LD(LoadTarget::B, LoadSource::N8)),
+            0x7 => Some(Instruction::RLCA),
+            0x8 => Some(Instruction::LDN16SP),
+            0x9 => Some(Instruction::ADDHL(AddHLSource::BC)),
+            0xa => Some(Instruction::LD(LoadTarget::A, LoadSource::BC_)),
+            0xb => Some(Instruction::DEC(DecrementTarget::BC)),
             0xc => Some(Instruction::INC(IncTarget::C)),
             0xd => Some(Instruction::DEC(DecrementTarget::C)),
             0xe => Some(Instruction::LD(LoadTarget::C, LoadSource::N8)),
+            0xf => Some(Instruction::RRCA),
+            0x10 => Some(Instruction::STOP),
             0x11 => Some(Instruction::LDN16(LoadTypeN16::DE)),
+            0x12 => Some(Instruction::LD(LoadTarget::DE_, LoadSource::A)),
             0x13 => Some(Instruction::INC(IncTarget::DE)),
+            0x14 => Some(Instruction::INC(IncTarget::D)),
             0x15 => Some(Instruction::DEC(DecrementTarget::D)),
             0x16 => Some(Instruction::LD(LoadTarget::D, LoadSource::N8)),
             0x17 => Some(Instruction::RLA),
             0x18 => Some(Instruction::JR(JumpCondition::Always)),
+            0x19 => Some(Instruction::ADDHL(AddHLSource::DE)),
             0x1a => Some(Instruction::LD(LoadTarget::A, LoadSource::DE_)),
+            0x1b => Some(Instruction::DEC(DecrementTarget::DE)),
+            0x1c => Some(Instruction::INC(IncTarget::E)),
             0x1d => Some(Instruction::DEC(DecrementTarget::E)),
             0x1e => Some(Instruction::LD(LoadTarget::E, LoadSource::N8)),
+            0x1f => Some(Instruction::RRA),
             0x20 => Some(Instruction::JR(JumpCondition::NZ)),
             0x21 => Some(Instruction::LDN16(LoadTypeN16::HL)),
             0x22 => Some(Instruction::LD(LoadTarget::HLI, LoadSource::A)),
             0x23 => Some(Instruction::INC(IncTarget::HL)),
             0x24 => Some(Instruction::INC(IncTarget::H)),
+            0x25 => Some(Instruction::DEC(DecrementTarget::H)),
+            0x26 => Some(Instruction::LD(LoadTarget::H, LoadSource::N8)),
+            0x27 => Some(Instruction::DAA),
             0x28 => Some(Instruction::JR(JumpCondition::Z)),
+            0x29 => Some(Instruction::ADDHL(AddHLSource::HL)),
+            0x2a => Some(Instruction::LD(LoadTarget::A, LoadSource::HLI)),
+            0x2b => Some(Instruction::DEC(DecrementTarget::HL)),
+            0x2c => Some(Instruction::INC(IncTarget::L)),
+            0x2d => Some(Instruction::DEC(DecrementTarget::L)),
             0x2e => Some(Instruction::LD(LoadTarget::L, LoadSource::N8)),
+            0x2f => Some(Instruction::CPL),
+            0x30 => Some(Instruction::JR(JumpCondition::NC)),
             0x31 => Some(Instruction::LDN16(LoadTypeN16::SP)),
             0x32 => Some(Instruction::LD(LoadTarget::HLD, LoadSource::A)),
+            0x33 => Some(Instruction::INC(IncTarget::SP)),
+            0x34 => Some(Instruction::INC(IncTarget::HL_)),
+            0x35 => Some(Instruction::DEC(DecrementTarget::HL_)),
+            0x36 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::N8)),
+            0x37 => Some(Instruction::SCF),
+            0x38 => Some(Instruction::JR(JumpCondition::C)),
+            0x39 => Some(Instruction::ADDHL(AddHLSource::SP)),
+            0x3a => Some(Instruction::LD(LoadTarget::A, LoadSource::HLD)),
+            0x3b => Some(Instruction::DEC(DecrementTarget::SP)),
+            0x3c => Some(Instruction::INC(IncTarget::A)),
             0x3d => Some(Instruction::DEC(DecrementTarget::A)),
             0x3e => Some(Instruction::LD(LoadTarget::A, LoadSource::N8)),
+            0x3f => Some(Instruction::CCF),
+            0x40 => Some(Instruction::LD(LoadTarget::B, LoadSource::B)),
+            0x41 => Some(Instruction::LD(LoadTarget::B, LoadSource::C)),
+            0x42 => Some(Instruction::LD(LoadTarget::B, LoadSource::D)),
+            0x43 => Some(Instruction::LD(LoadTarget::B, LoadSource::E)),
+            0x44 => Some(Instruction::LD(LoadTarget::B, LoadSource::H)),
+            0x45 => Some(Instruction::LD(LoadTarget::B, LoadSource::L)),
+            0x46 => Some(Instruction::LD(LoadTarget::B, LoadSource::HL_)),
+            0x47 => Some(Instruction::LD(LoadTarget::B, LoadSource::A)),
+            0x48 => Some(Instruction::LD(LoadTarget::C, LoadSource::B)),
+            0x49 => Some(Instruction::LD(LoadTarget::C, LoadSource::C)),
+            0x4a => Some(Instruction::LD(LoadTarget::C, LoadSource::D)),
+            0x4b => Some(Instruction::LD(LoadTarget::C, LoadSource::E)),
+            0x4c => Some(Instruction::LD(LoadTarget::C, LoadSource::H)),
+            0x4d => Some(Instruction::LD(LoadTarget::C, LoadSource::L)),
+            0x4e => Some(Instruction::LD(LoadTarget::C, LoadSource::HL_)),
             0x4f => Some(Instruction::LD(LoadTarget::C, LoadSource::A)),
+            0x50 => Some(Instruction::LD(LoadTarget::D, LoadSource::B)),
+            0x51 => Some(Instruction::LD(LoadTarget::D, LoadSource::C)),
+            0x52 => Some(Instruction::LD(LoadTarget::D, LoadSource::D)),
+            0x53 => Some(Instruction::LD(LoadTarget::D, LoadSource::E)),
+            0x54 => Some(Instruction::LD(LoadTarget::D, LoadSource::H)),
+            0x55 => Some(Instruction::LD(LoadTarget::D, LoadSource::L)),
+            0x56 => Some(Instruction::LD(LoadTarget::D, LoadSource::HL_)),
             0x57 => Some(Instruction::LD(LoadTarget::D, LoadSource::A)),
+            0x58 => Some(Instruction::LD(LoadTarget::E, LoadSource::B)),
+            0x59 => Some(Instruction::LD(LoadTarget::E, LoadSource::C)),
+            0x5a => Some(Instruction::LD(LoadTarget::E, LoadSource::D)),
+            0x5b => Some(Instruction::LD(LoadTarget::E, LoadSource::E)),
+            0x5c => Some(Instruction::LD(LoadTarget::E, LoadSource::H)),
+            0x5d => Some(Instruction::LD(LoadTarget::E, LoadSource::L)),
+            0x5e => Some(Instruction::LD(LoadTarget::E, LoadSource::HL_)),
+            0x5f => Some(Instruction::LD(LoadTarget::E, LoadSource::A)),
+            0x60 => Some(Instruction::LD(LoadTarget::H, LoadSource::B)),
+            0x61 => Some(Instruction::LD(LoadTarget::H, LoadSource::C)),
+            0x62 => Some(Instruction::LD(LoadTarget::H, LoadSource::D)),
+            0x63 => Some(Instruction::LD(LoadTarget::H, LoadSource::E)),
+            0x64 => Some(Instruction::LD(LoadTarget::H, LoadSource::H)),
+            0x65 => Some(Instruction::LD(LoadTarget::H, LoadSource::L)),
+            0x66 => Some(Instruction::LD(LoadTarget::H, LoadSource::HL_)),
             0x67 => Some(Instruction::LD(LoadTarget::H, LoadSource::A)),
+            0x68 => Some(Instruction::LD(LoadTarget::L, LoadSource::B)),
+            0x69 => Some(Instruction::LD(LoadTarget::L, LoadSource::C)),
+            0x6a => Some(Instruction::LD(LoadTarget::L, LoadSource::D)),
+            0x6b => Some(Instruction::LD(LoadTarget::L, LoadSource::E)),
+            0x6c => Some(Instruction::LD(LoadTarget::L, LoadSource::H)),
+            0x6d => Some(Instruction::LD(LoadTarget::L, LoadSource::L)),
+            0x6e => Some(Instruction::LD(LoadTarget::L, LoadSource::HL_)),
+            0x6f => Some(Instruction::LD(LoadTarget::L, LoadSource::A)),
+            0x70 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::B)),
+            0x71 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::C)),
+            0x72 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::D)),
+            0x73 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::E)),
+            0x74 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::H)),
+            0x75 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::L)),
+            0x76 => Some(Instruction::HALT),
             0x77 => Some(Instruction::LD(LoadTarget::HL_, LoadSource::A)),
             0x78 => Some(Instruction::LD(LoadTarget::A, LoadSource::B)),
+            0x79 => Some(Instruction::LD(LoadTarget::A, LoadSource::C)),
+            0x7a => Some(Instruction::LD(LoadTarget::A, LoadSource::D)),
             0x7b => Some(Instruction::LD(LoadTarget::A, LoadSource::E)),
             0x7c => Some(Instruction::LD(LoadTarget::A, LoadSource::H)),
             0x7d => Some(Instruction::LD(LoadTarget::A, LoadSource::L)),
+            0x7e => Some(Instruction::LD(LoadTarget::A, LoadSource::HL_)),
+            0x7f => Some(Instruction::LD(LoadTarget::A, LoadSource::A)),
+            0x80 => Some(Instruction::ADD(ArithmeticSource::B)),
+            0x81 => Some(Instruction::ADD(ArithmeticSource::C)),
+            0x82 => Some(Instruction::ADD(ArithmeticSource::D)),
+            0x83 => Some(Instruction::ADD(ArithmeticSource::E)),
+            0x84 => Some(Instruction::ADD(ArithmeticSource::H)),
+            0x85 => Some(Instruction::ADD(ArithmeticSource::L)),
             0x86 => Some(Instruction::ADD(ArithmeticSource::HL_)),
+            0x87 => Some(Instruction::ADD(ArithmeticSource::A)),
+            0x88 => Some(Instruction::ADC(ArithmeticSource::B)),
+            0x89 => Some(Instruction::ADC(ArithmeticSource::C)),
+            0x8a => Some(Instruction::ADC(ArithmeticSource::D)),
+            0x8b => Some(Instruction::ADC(ArithmeticSource::E)),
+            0x8c => Some(Instruction::ADC(ArithmeticSource::H)),
+            0x8d => Some(Instruction::ADC(ArithmeticSource::L)),
+            0x8e => Some(Instruction::ADC(ArithmeticSource::HL_)),
+            0x8f => Some(Instruction::ADC(ArithmeticSource::A)),
             0x90 => Some(Instruction::SUB(ArithmeticSource::B)),
-            0xAF => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::A)),
+            0x91 => Some(Instruction::SUB(ArithmeticSource::C)),
+            0x92 => Some(Instruction::SUB(ArithmeticSource::D)),
+            0x93 => Some(Instruction::SUB(ArithmeticSource::E)),
+            0x94 => Some(Instruction::SUB(ArithmeticSource::H)),
+            0x95 => Some(Instruction::SUB(ArithmeticSource::L)),
+            0x96 => Some(Instruction::SUB(ArithmeticSource::HL_)),
+            0x97 => Some(Instruction::SUB(ArithmeticSource::A)),
+            0x98 => Some(Instruction::SBC(ArithmeticSource::B)),
+            0x99 => Some(Instruction::SBC(ArithmeticSource::C)),
+            0x9a => Some(Instruction::SBC(ArithmeticSource::D)),
+            0x9b => Some(Instruction::SBC(ArithmeticSource::E)),
+            0x9c => Some(Instruction::SBC(ArithmeticSource::H)),
+            0x9d => Some(Instruction::SBC(ArithmeticSource::L)),
+            0x9e => Some(Instruction::SBC(ArithmeticSource::HL_)),
+            0x9f => Some(Instruction::SBC(ArithmeticSource::A)),
+            0xa0 => Some(Instruction::AND(ArithmeticSource::B)),
+            0xa1 => Some(Instruction::AND(ArithmeticSource::C)),
+            0xa2 => Some(Instruction::AND(ArithmeticSource::D)),
+            0xa3 => Some(Instruction::AND(ArithmeticSource::E)),
+            0xa4 => Some(Instruction::AND(ArithmeticSource::H)),
+            0xa5 => Some(Instruction::AND(ArithmeticSource::L)),
+            0xa6 => Some(Instruction::AND(ArithmeticSource::HL_)),
+            0xa7 => Some(Instruction::AND(ArithmeticSource::A)),
+            0xa8 => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::B)),
+            0xa9 => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::C)),
+            0xaa => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::D)),
+            0xab => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::E)),
+            0xac => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::H)),
+            0xad => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::L)),
+            0xae => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::HL_)),
+            0xaf => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::A)),
+            0xb0 => Some(Instruction::OR(ArithmeticSource::B)),
+            0xb1 => Some(Instruction::OR(ArithmeticSource::C)),
+            0xb2 => Some(Instruction::OR(ArithmeticSource::D)),
+            0xb3 => Some(Instruction::OR(ArithmeticSource::E)),
+            0xb4 => Some(Instruction::OR(ArithmeticSource::H)),
+            0xb5 => Some(Instruction::OR(ArithmeticSource::L)),
+            0xb6 => Some(Instruction::OR(ArithmeticSource::HL_)),
+            0xb7 => Some(Instruction::OR(ArithmeticSource::A)),
+            0xb8 => Some(Instruction::CP(CompareSource::B)),
+            0xb9 => Some(Instruction::CP(CompareSource::C)),
+            0xba => Some(Instruction::CP(CompareSource::D)),
+            0xbb => Some(Instruction::CP(CompareSource::E)),
+            0xbc => Some(Instruction::CP(CompareSource::H)),
+            0xbd => Some(Instruction::CP(CompareSource::L)),
             0xbe => Some(Instruction::CP(CompareSource::HL_)),
+            0xbf => Some(Instruction::CP(CompareSource::A)),
+            0xc0 => Some(Instruction::RET(JumpCondition::NZ)),
             0xc1 => Some(Instruction::POP(PopTarget::BC)),
+            0xc2 => Some(Instruction::JP(JumpCondition::NZ)),
+            0xc3 => Some(Instruction::JP(JumpCondition::Always)),
+            0xc4 => Some(Instruction::CALL(JumpCondition::NZ)),
             0xc5 => Some(Instruction::PUSH(PushTarget::BC)),
-            0xc9 => Some(Instruction::RET),
-            0xcd => Some(Instruction::CALL),
+            0xc6 => Some(Instruction::ADD(ArithmeticSource::N8)),
+            0xc7 => Some(Instruction::RST(0x0)),
+            0xc8 => Some(Instruction::RET(JumpCondition::Z)),
+            0xc9 => Some(Instruction::RET(JumpCondition::Always)),
+            0xca => Some(Instruction::JP(JumpCondition::Z)),
+            0xcc => Some(Instruction::CALL(JumpCondition::Z)),
+            0xcd => Some(Instruction::CALL(JumpCondition::Always)),
+            0xce => Some(Instruction::ADC(ArithmeticSource::N8)),
+            0xcf => Some(Instruction::RST(0x8)),
+            0xd0 => Some(Instruction::RET(JumpCondition::NC)),
+            0xd1 => Some(Instruction::POP(PopTarget::DE)),
+            0xd2 => Some(Instruction::JP(JumpCondition::NC)),
+            0xd4 => Some(Instruction::CALL(JumpCondition::NC)),
+            0xd5 => Some(Instruction::PUSH(PushTarget::DE)),
+            0xd6 => Some(Instruction::SUB(ArithmeticSource::N8)),
+            0xd7 => Some(Instruction::RST(0x10)),
+            0xd8 => Some(Instruction::RET(JumpCondition::C)),
+            0xd9 => Some(Instruction::RETI),
+            0xda => Some(Instruction::JP(JumpCondition::C)),
+            0xdc => Some(Instruction::CALL(JumpCondition::C)),
+            0xde => Some(Instruction::SBC(ArithmeticSource::N8)),
+            0xdf => Some(Instruction::RST(0x18)),
             0xe0 => Some(Instruction::LDH(LoadHTarget::N8_, LoadHSource::A)),
+            0xe1 => Some(Instruction::POP(PopTarget::HL)),
             0xe2 => Some(Instruction::LDH(LoadHTarget::C_, LoadHSource::A)),
+            0xe5 => Some(Instruction::PUSH(PushTarget::HL)),
+            0xe6 => Some(Instruction::AND(ArithmeticSource::N8)),
+            0xe7 => Some(Instruction::RST(0x20)),
+            0xe8 => Some(Instruction::ADDSP),
+            0xe9 => Some(Instruction::JPHL),
             0xea => Some(Instruction::LD(LoadTarget::N16_, LoadSource::A)),
+            0xee => Some(Instruction::XOR(ArithmeticTarget::A, ArithmeticSource::N8)),
+            0xef => Some(Instruction::RST(0x28)),
             0xf0 => Some(Instruction::LDH(LoadHTarget::A, LoadHSource::N8_)),
+            0xf1 => Some(Instruction::POP(PopTarget::AF)),
+            0xf2 => Some(Instruction::LDH(LoadHTarget::A, LoadHSource::C_)),
+            0xf3 => Some(Instruction::DI),
+            0xf5 => Some(Instruction::PUSH(PushTarget::AF)),
+            0xf6 => Some(Instruction::OR(ArithmeticSource::N8)),
+            0xf7 => Some(Instruction::RST(0x30)),
+            0xf8 => Some(Instruction::LDHLSP),
+            0xf9 => Some(Instruction::LDSPHL),
+            0xfa => Some(Instruction::LD(LoadTarget::A, LoadSource::N16_)),
+            0xfb => Some(Instruction::EI),
             0xfe => Some(Instruction::CP(CompareSource::N8)),
-            _ =>
-            /* TODO: Add mapping for rest of instructions */
-            {
-                None
-            }
+            0xff => Some(Instruction::RST(0x38)),
+            // 0xd3, 0xdb, 0xdd, 0xe3, 0xe4, 0xeb, 0xec, 0xed, 0xf4, 0xfc and 0xfd are not valid
+            // opcodes, and 0xcb is the prefix byte.
+            _ => None,
         }
     }
 }
@@ -0,0 +1,35 @@
+// Interrupt sources in priority order, highest first. Each one owns the bit of IF and IE matching
+// its position and is serviced at 0x40 + 8 * position.
+#[derive(Clone, Copy)]
+pub enum Interrupt {
+    VBlank,
+    Stat,
+    Timer,
+    Serial,
+    Joypad,
+}
+
+pub const INTERRUPTS: [Interrupt; 5] = [
+    Interrupt::VBlank,
+    Interrupt::Stat,
+    Interrupt::Timer,
+    Interrupt::Serial,
+    Interrupt::Joypad,
+];
+
+impl Interrupt {
+    pub fn mask(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn handler_address(self) -> u16 {
+        0x40 + 8 * self as u16
+    }
+
+    // The highest priority interrupt among the given IF & IE bits.
+    pub fn highest_priority(pending: u8) -> Option<Interrupt> {
+        INTERRUPTS
+            .into_iter()
+            .find(|interrupt| pending & interrupt.mask() != 0)
+    }
+}
@@ -1,39 +1,457 @@
+use std::rc::Rc;
+
+use super::interrupts::Interrupt;
+use crate::boot_rom::BootRom;
+use crate::cartridge::header::CartridgeHeader;
+use crate::cartridge::rtc::RtcMode;
+use crate::cartridge::{self, Cartridge};
+use crate::infrared::{Disconnected, Infrared};
+use crate::joypad::{Button, Joypad};
+use crate::timer::Timer;
+
+// T-cycles the LCD spends on each scanline.
+pub const CYCLES_PER_LINE: u32 = 456;
+// Bytes copied to OAM by a DMA transfer, one per M-cycle.
+const DMA_LENGTH: u16 = 0xa0;
+
+// Bits of each I/O register at 0xFF00-0xFF7F that are not implemented and always read as 1.
+// Registers that do not exist on DMG read as 0xFF.
+#[rustfmt::skip]
+const IO_READ_MASKS: [u8; 0x80] = [
+    0xc0, 0x00, 0x7e, 0xff, 0x00, 0x00, 0x00, 0xf8, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xe0,
+    0x80, 0x3f, 0x00, 0xff, 0xbf, 0xff, 0x3f, 0x00, 0xff, 0xbf, 0x7f, 0xff, 0x9f, 0xff, 0xbf, 0xff,
+    0xff, 0x00, 0x00, 0xbf, 0x00, 0x00, 0x70, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x80, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+];
+
+// Told whenever the cartridge's rumble motor turns on or off.
+pub type RumbleCallback = Box<dyn FnMut(bool)>;
+// Told whenever the cartridge's speaker starts or stops its tone.
+pub type ToneCallback = Box<dyn FnMut(bool)>;
+
+// LCD registers the line counter below works with, until the PPU is wired to the bus.
+const STAT: usize = 0x41;
+const LY: usize = 0x44;
+const LYC: usize = 0x45;
+// CGB infrared port.
+const RP: usize = 0x56;
+
+// Routes each region of the address space to its owner:
+//
+// 0x0000-0x7FFF  cartridge ROM, partly covered by the boot ROM until 0xFF50 is written
+// 0x8000-0x9FFF  VRAM
+// 0xA000-0xBFFF  cartridge RAM
+// 0xC000-0xDFFF  WRAM, mirrored by echo RAM at 0xE000-0xFDFF
+// 0xFE00-0xFE9F  OAM
+// 0xFEA0-0xFEFF  unusable
+// 0xFF00-0xFF7F  I/O registers
+// 0xFF80-0xFFFE  HRAM
+// 0xFFFF         IE
 pub struct MemoryBus {
-    memory: [u8; 0xFFFF],
+    boot_rom: Option<BootRom>,
+    cartridge: Box<dyn Cartridge>,
+    // Missing for an empty slot and for cartridges plugged in without a ROM header.
+    cartridge_header: Option<CartridgeHeader>,
+    // Whether the cartridge reported changes to its save data since it was last flushed.
+    save_dirty: bool,
+    // Kept here so it also applies to cartridges loaded later.
+    rtc_mode: RtcMode,
+    rumble_callback: Option<RumbleCallback>,
+    // Last rumble state reported to the callback.
+    rumble: bool,
+    tone_callback: Option<ToneCallback>,
+    tone: bool,
+    // Shared by the CGB port and the cartridge.
+    infrared: Rc<dyn Infrared>,
+    vram: [u8; 0x2000],
+    wram: [u8; 0x2000],
+    oam: [u8; 0xa0],
+    io: [u8; 0x80],
+    hram: [u8; 0x7f],
+    timer: Timer,
+    joypad: Joypad,
+    // IF at 0xFF0F and IE at 0xFFFF.
+    interrupt_flag: u8,
+    interrupt_enable: u8,
+
+    // Whether CGB registers such as KEY1 and RP are present. Set by the CGB boot ROM unless it
+    // drops to DMG compatibility through KEY0, or by skipping the boot ROM on a CGB model.
+    cgb_mode: bool,
+    // KEY1 at 0xFF4D.
+    speed_switch_armed: bool,
+    double_speed: bool,
+
+    // T-cycles spent on the current scanline.
+    line_cycles: u32,
+    // Source page of the running OAM DMA transfer and how many bytes it has copied so far.
+    dma_source: u16,
+    dma_progress: Option<u16>,
 }
 
 impl MemoryBus {
     pub fn read_byte(&self, address: u16) -> u8 {
-        self.memory[address as usize]
+        // While OAM DMA runs, the CPU can only reach I/O registers and HRAM.
+        if self.dma_progress.is_some() && address < 0xff00 {
+            return 0xff;
+        }
+        self.read_mapped(address)
     }
 
     pub fn read_signed_byte(&self, address: u16) -> i8 {
-        self.memory[address as usize] as i8
+        self.read_byte(address) as i8
     }
 
     pub fn write_byte(&mut self, address: u16, value: u8) {
-        self.memory[address as usize] = value;
+        if self.dma_progress.is_some() && address < 0xff00 {
+            return;
+        }
+        match address {
+            0x0000..=0x7fff => {
+                self.cartridge.write_rom(address, value);
+                self.update_cartridge_outputs();
+            }
+            0x8000..=0x9fff => self.vram[(address - 0x8000) as usize] = value,
+            0xa000..=0xbfff => {
+                self.cartridge.write_ram(address, value);
+                self.update_cartridge_outputs();
+            }
+            0xc000..=0xdfff => self.wram[(address - 0xc000) as usize] = value,
+            0xe000..=0xfdff => self.wram[(address - 0xe000) as usize] = value,
+            0xfe00..=0xfe9f => self.oam[(address - 0xfe00) as usize] = value,
+            0xfea0..=0xfeff => {}
+            0xff00..=0xff7f => self.write_io(address, value),
+            0xff80..=0xfffe => self.hram[(address - 0xff80) as usize] = value,
+            0xffff => self.interrupt_enable = value,
+        }
     }
 
-    pub fn write_array(&mut self, address: u16, value: &[u8]) {
-        for i in 0..value.len() - 1 {
-            self.memory[address as usize + i] = value[i as usize];
+    pub fn load_boot_rom(&mut self, boot_rom: BootRom) {
+        self.cgb_mode = boot_rom.is_cgb();
+        self.boot_rom = Some(boot_rom);
+        self.cartridge.set_boot_rom(self.boot_rom.as_ref());
+    }
+
+    pub fn load_cartridge(
+        &mut self,
+        cartridge: Box<dyn Cartridge>,
+        cartridge_header: Option<CartridgeHeader>,
+    ) {
+        self.cartridge = cartridge;
+        self.cartridge_header = cartridge_header;
+        self.save_dirty = false;
+        self.cartridge.set_rtc_mode(self.rtc_mode);
+        self.cartridge.set_infrared(self.infrared.clone());
+        self.cartridge.set_boot_rom(self.boot_rom.as_ref());
+        self.update_cartridge_outputs();
+    }
+
+    pub fn set_infrared(&mut self, infrared: Rc<dyn Infrared>) {
+        self.infrared = infrared;
+        self.cartridge.set_infrared(self.infrared.clone());
+    }
+
+    pub fn set_tilt(&mut self, x: f32, y: f32) {
+        self.cartridge.set_tilt(x, y);
+    }
+
+    pub fn set_rumble_callback(&mut self, callback: RumbleCallback) {
+        self.rumble_callback = Some(callback);
+    }
+
+    pub fn set_tone_callback(&mut self, callback: ToneCallback) {
+        self.tone_callback = Some(callback);
+    }
+
+    pub fn set_rtc_mode(&mut self, mode: RtcMode) {
+        self.rtc_mode = mode;
+        self.cartridge.set_rtc_mode(mode);
+    }
+
+    pub fn cartridge(&self) -> &dyn Cartridge {
+        self.cartridge.as_ref()
+    }
+
+    pub fn cartridge_mut(&mut self) -> &mut dyn Cartridge {
+        self.cartridge.as_mut()
+    }
+
+    pub fn cartridge_header(&self) -> Option<&CartridgeHeader> {
+        self.cartridge_header.as_ref()
+    }
+
+    pub fn save_dirty(&mut self) -> bool {
+        self.save_dirty |= self.cartridge.take_save_dirty();
+        self.save_dirty
+    }
+
+    // Called once the save data has been written out.
+    pub fn mark_saved(&mut self) {
+        self.save_dirty = false;
+    }
+
+    // Stores an I/O register without the side effects of writing to it, e.g. starting a DMA.
+    pub fn set_io_register(&mut self, address: u16, value: u8) {
+        match address {
+            0xff00 => {
+                self.joypad.write(value);
+            }
+            0xff04 => self.timer.set_counter((value as u16) << 8),
+            0xff05..=0xff07 => self.timer.write_byte(address, value),
+            0xff0f => self.interrupt_flag = value & 0x1f,
+            _ => self.io[(address - 0xff00) as usize] = value,
         }
     }
 
+    pub fn set_div_counter(&mut self, counter: u16) {
+        self.timer.set_counter(counter);
+    }
+
     pub fn read_ly(&self) -> u8 {
-        self.memory[0xff44]
+        self.io[LY]
     }
 
     pub fn write_ly(&mut self, value: u8) {
-        self.memory[0xff44] = value;
+        self.io[LY] = value;
+    }
+
+    pub fn request_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag |= interrupt.mask();
+    }
+
+    pub fn acknowledge_interrupt(&mut self, interrupt: Interrupt) {
+        self.interrupt_flag &= !interrupt.mask();
+    }
+
+    // Interrupts that are both requested and enabled.
+    pub fn pending_interrupts(&self) -> u8 {
+        self.interrupt_flag & self.interrupt_enable & 0x1f
+    }
+
+    pub fn set_cgb_mode(&mut self, cgb_mode: bool) {
+        self.cgb_mode = cgb_mode;
+    }
+
+    pub fn set_button(&mut self, button: Button, pressed: bool) {
+        if self.joypad.set_button(button, pressed) {
+            self.request_interrupt(Interrupt::Joypad);
+        }
+    }
+
+    pub fn joypad_input_low(&self) -> bool {
+        self.joypad.input_low()
+    }
+
+    pub fn speed_switch_armed(&self) -> bool {
+        self.speed_switch_armed
+    }
+
+    // Performs the CGB speed switch requested through KEY1 when STOP executes.
+    pub fn switch_speed(&mut self) {
+        self.double_speed = !self.double_speed;
+        self.speed_switch_armed = false;
+    }
+
+    // T-cycles until the timer or LCD next has something to do, used to skip ahead while the CPU
+    // is halted.
+    pub fn cycles_until_next_event(&self) -> u32 {
+        let mut line_remaining = CYCLES_PER_LINE - self.line_cycles;
+        if self.double_speed {
+            line_remaining *= 2;
+        }
+        let cycles = match self.timer.cycles_until_interrupt() {
+            Some(timer_remaining) => line_remaining.min(timer_remaining),
+            None => line_remaining,
+        };
+        // The bus only advances in whole M-cycles.
+        cycles.div_ceil(4).max(1) * 4
+    }
+
+    // Advances the timer, the LCD line counter, OAM DMA and the cartridge by the given number of
+    // T-cycles. In CGB double speed mode the LCD and the cartridge clock run at half the rate of
+    // the CPU.
+    pub fn tick(&mut self, cycles: u32) {
+        if self.timer.tick(cycles) {
+            self.request_interrupt(Interrupt::Timer);
+        }
+
+        let base_cycles = if self.double_speed {
+            cycles / 2
+        } else {
+            cycles
+        };
+        self.cartridge.tick(base_cycles);
+
+        self.line_cycles += base_cycles;
+        if self.line_cycles >= CYCLES_PER_LINE {
+            self.line_cycles -= CYCLES_PER_LINE;
+            let mut ly = self.read_ly();
+            ly += 1;
+            if ly > 153 {
+                ly = 0;
+            }
+            self.write_ly(ly);
+
+            if ly == 144 {
+                self.request_interrupt(Interrupt::VBlank);
+            }
+            // LY=LYC STAT interrupt, when enabled by bit 6 of STAT.
+            if ly == self.io[LYC] && self.io[STAT] & 0x40 != 0 {
+                self.request_interrupt(Interrupt::Stat);
+            }
+        }
+
+        for _ in 0..cycles / 4 {
+            self.tick_dma();
+        }
+    }
+
+    // Reads without the restrictions OAM DMA puts on the CPU.
+    fn read_mapped(&self, address: u16) -> u8 {
+        if let Some(value) = self
+            .boot_rom
+            .as_ref()
+            .and_then(|boot_rom| boot_rom.read(address))
+        {
+            return value;
+        }
+        match address {
+            0x0000..=0x7fff => self.cartridge.read_rom(address),
+            0x8000..=0x9fff => self.vram[(address - 0x8000) as usize],
+            0xa000..=0xbfff => self.cartridge.read_ram(address),
+            0xc000..=0xdfff => self.wram[(address - 0xc000) as usize],
+            0xe000..=0xfdff => self.wram[(address - 0xe000) as usize],
+            0xfe00..=0xfe9f => self.oam[(address - 0xfe00) as usize],
+            0xfea0..=0xfeff => 0x00,
+            0xff00..=0xff7f => self.read_io(address),
+            0xff80..=0xfffe => self.hram[(address - 0xff80) as usize],
+            0xffff => self.interrupt_enable,
+        }
+    }
+
+    fn read_io(&self, address: u16) -> u8 {
+        match address {
+            0xff00 => self.joypad.read(),
+            0xff04..=0xff07 => self.timer.read_byte(address),
+            0xff0f => self.interrupt_flag | 0xe0,
+            0xff4d if self.cgb_mode => {
+                (self.double_speed as u8) << 7 | 0x7e | self.speed_switch_armed as u8
+            }
+            // Bit 1 reads 0 while light is received, but only with reading enabled by bits 6-7.
+            0xff56 if self.cgb_mode => {
+                let value = self.io[RP] & 0xc1 | 0x3e;
+                if value & 0xc0 == 0xc0 && self.infrared.receiving() {
+                    value & !0x02
+                } else {
+                    value
+                }
+            }
+            _ => {
+                let index = (address - 0xff00) as usize;
+                self.io[index] | IO_READ_MASKS[index]
+            }
+        }
+    }
+
+    fn write_io(&mut self, address: u16, value: u8) {
+        match address {
+            0xff00 => {
+                if self.joypad.write(value) {
+                    self.request_interrupt(Interrupt::Joypad);
+                }
+            }
+            0xff04..=0xff07 => self.timer.write_byte(address, value),
+            0xff0f => self.interrupt_flag = value & 0x1f,
+            0xff44 => {}
+            0xff46 => {
+                self.io[0x46] = value;
+                self.dma_source = (value as u16) << 8;
+                self.dma_progress = Some(0);
+            }
+            // KEY0. The CGB boot ROM sets bit 2 to run a DMG cartridge in compatibility mode.
+            0xff4c if self.boot_rom.is_some() => self.cgb_mode &= value & 0x04 == 0,
+            0xff4d if self.cgb_mode => self.speed_switch_armed = value & 0x1 != 0,
+            // Bit 0 drives the LED.
+            0xff56 if self.cgb_mode => {
+                self.io[RP] = value;
+                self.infrared.set_emitting(value & 0x01 != 0);
+            }
+            // Unmaps the boot ROM for good.
+            0xff50 => {
+                if value != 0 && self.boot_rom.is_some() {
+                    self.boot_rom = None;
+                    self.cartridge.set_boot_rom(None);
+                }
+            }
+            _ => self.io[(address - 0xff00) as usize] = value,
+        }
+    }
+
+    // Reports changes to the rumble motor and speaker after the cartridge was written to.
+    fn update_cartridge_outputs(&mut self) {
+        let rumble = self.cartridge.rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            if let Some(callback) = &mut self.rumble_callback {
+                callback(rumble);
+            }
+        }
+
+        let tone = self.cartridge.tone();
+        if tone != self.tone {
+            self.tone = tone;
+            if let Some(callback) = &mut self.tone_callback {
+                callback(tone);
+            }
+        }
+    }
+
+    fn tick_dma(&mut self) {
+        if let Some(progress) = self.dma_progress {
+            let value = self.read_mapped(self.dma_source + progress);
+            self.oam[progress as usize] = value;
+            self.dma_progress = if progress + 1 < DMA_LENGTH {
+                Some(progress + 1)
+            } else {
+                None
+            };
+        }
     }
 }
 
 impl Default for MemoryBus {
     fn default() -> Self {
         Self {
-            memory: [0; 0xFFFF],
+            boot_rom: None,
+            cartridge: cartridge::empty(),
+            cartridge_header: None,
+            save_dirty: false,
+            rtc_mode: RtcMode::WallClock,
+            rumble_callback: None,
+            rumble: false,
+            tone_callback: None,
+            tone: false,
+            infrared: Rc::new(Disconnected),
+            vram: [0; 0x2000],
+            wram: [0; 0x2000],
+            oam: [0; 0xa0],
+            io: [0; 0x80],
+            hram: [0; 0x7f],
+            timer: Timer::default(),
+            joypad: Joypad::default(),
+            interrupt_flag: 0,
+            interrupt_enable: 0,
+
+            cgb_mode: false,
+            speed_switch_armed: false,
+            double_speed: false,
+
+            line_cycles: 0,
+            dma_source: 0,
+            dma_progress: None,
         }
     }
 }
@@ -1,20 +1,33 @@
+use super::flag_registers::FlagsRegister;
+
+#[derive(Default)]
 pub struct Registers {
     pub a: u8,
     pub b: u8,
     pub c: u8,
     pub d: u8,
     pub e: u8,
-    f: u8,
+    pub f: FlagsRegister,
     pub h: u8,
     pub l: u8,
 }
 
 impl Registers {
-    fn get_bc(&self) -> u16 {
+    pub fn get_af(&self) -> u16 {
+        (self.a as u16) << 8 | u8::from(self.f) as u16
+    }
+
+    // The lower nibble of F does not exist in hardware and always reads back as zero.
+    pub fn set_af(&mut self, value: u16) {
+        self.a = ((value & 0xFF00) >> 8) as u8;
+        self.f = FlagsRegister::from((value & 0xF0) as u8);
+    }
+
+    pub fn get_bc(&self) -> u16 {
         (self.b as u16) << 8 | self.c as u16
     }
 
-    fn set_bc(&mut self, value: u16) {
+    pub fn set_bc(&mut self, value: u16) {
         self.b = ((value & 0xFF00) >> 8) as u8;
         self.c = (value & 0xFF) as u8;
     }
@@ -37,18 +50,3 @@ impl Registers {
         self.l = (value & 0xFF) as u8;
     }
 }
-
-impl Default for Registers {
-    fn default() -> Self {
-        Self {
-            a: 0,
-            b: 0,
-            c: 0,
-            d: 0,
-            e: 0,
-            f: 0,
-            h: 0,
-            l: 0,
-        }
-    }
-}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+pub enum EmuError {
+    // An opcode that does not decode to an instruction was fetched while the illegal opcode policy
+    // is `Error`. `cycles` are the T-cycles spent fetching it, which the rest of the machine has
+    // already been advanced by.
+    UnknownOpcode {
+        pc: u16,
+        opcode: u8,
+        prefixed: bool,
+        cycles: u32,
+    },
+    // Same, with the policy set to `Break`. PC still points at the opcode.
+    Breakpoint {
+        pc: u16,
+        opcode: u8,
+        cycles: u32,
+    },
+    // The file is too short to hold a cartridge header.
+    TruncatedRom {
+        size: usize,
+    },
+    RomSizeMismatch {
+        header_size: usize,
+        file_size: usize,
+    },
+    UnknownRomSize {
+        code: u8,
+    },
+    UnknownRamSize {
+        code: u8,
+    },
+    InvalidBootRomSize {
+        size: usize,
+    },
+    UnknownCartridgeType {
+        cartridge_type: u8,
+    },
+    // A known cartridge type whose mapper is not emulated.
+    UnsupportedCartridgeType {
+        cartridge_type: u8,
+    },
+    Io {
+        path: String,
+        error: std::io::Error,
+    },
+    // A save state that doesn't fit the cartridge it is loaded into.
+    InvalidState {
+        reason: &'static str,
+    },
+    // An image file that couldn't be decoded or encoded.
+    Image {
+        path: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EmuError::UnknownOpcode {
+                pc,
+                opcode,
+                prefixed,
+                ..
+            } => write!(
+                f,
+                "unknown opcode 0x{}{:02x} at 0x{:04x}",
+                if *prefixed { "cb" } else { "" },
+                opcode,
+                pc
+            ),
+            EmuError::Breakpoint { pc, opcode, .. } => {
+                write!(
+                    f,
+                    "break on illegal opcode 0x{:02x} at 0x{:04x}",
+                    opcode, pc
+                )
+            }
+            EmuError::TruncatedRom { size } => {
+                write!(f, "ROM of {} bytes is too short to hold a header", size)
+            }
+            EmuError::RomSizeMismatch {
+                header_size,
+                file_size,
+            } => write!(
+                f,
+                "header declares a {} byte ROM but the file has {} bytes",
+                header_size, file_size
+            ),
+            EmuError::UnknownRomSize { code } => write!(f, "unknown ROM size code 0x{:02x}", code),
+            EmuError::UnknownRamSize { code } => write!(f, "unknown RAM size code 0x{:02x}", code),
+            EmuError::InvalidBootRomSize { size } => {
+                write!(f, "invalid boot ROM size of {} bytes", size)
+            }
+            EmuError::UnknownCartridgeType { cartridge_type } => {
+                write!(f, "unknown cartridge type 0x{:02x}", cartridge_type)
+            }
+            EmuError::UnsupportedCartridgeType { cartridge_type } => {
+                write!(f, "unsupported cartridge type 0x{:02x}", cartridge_type)
+            }
+            EmuError::Io { path, error } => write!(f, "{}: {}", path, error),
+            EmuError::InvalidState { reason } => write!(f, "invalid save state: {}", reason),
+            EmuError::Image { path, reason } => write!(f, "{}: {}", path, reason),
+        }
+    }
+}
+
+impl fmt::Debug for EmuError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for EmuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EmuError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+// Reads a whole file, keeping its path for the error message.
+pub fn read_file(path: &str) -> Result<Vec<u8>, EmuError> {
+    std::fs::read(path).map_err(|error| EmuError::Io {
+        path: path.to_string(),
+        error,
+    })
+}
@@ -0,0 +1,54 @@
+use std::cell::Cell;
+use std::rc::Rc;
+
+// An infrared LED and receiver, as found in the CGB port at 0xFF56 and in HuC1/HuC3 cartridges.
+// Implementations decide where the light goes, e.g. to another emulator instance.
+pub trait Infrared {
+    // Turns our LED on or off.
+    fn set_emitting(&self, on: bool);
+    // Whether light from the other side is reaching our receiver.
+    fn receiving(&self) -> bool;
+}
+
+// Nothing to talk to: our light goes nowhere and none comes in.
+pub struct Disconnected;
+
+impl Infrared for Disconnected {
+    fn set_emitting(&self, _on: bool) {}
+
+    fn receiving(&self) -> bool {
+        false
+    }
+}
+
+// One end of a link between two instances in the same process, created by `link`.
+pub struct LinkedInfrared {
+    outgoing: Rc<Cell<bool>>,
+    incoming: Rc<Cell<bool>>,
+}
+
+impl Infrared for LinkedInfrared {
+    fn set_emitting(&self, on: bool) {
+        self.outgoing.set(on);
+    }
+
+    fn receiving(&self) -> bool {
+        self.incoming.get()
+    }
+}
+
+// Two ends pointed at each other: what one emits, the other receives.
+pub fn link() -> (LinkedInfrared, LinkedInfrared) {
+    let a_to_b = Rc::new(Cell::new(false));
+    let b_to_a = Rc::new(Cell::new(false));
+    (
+        LinkedInfrared {
+            outgoing: a_to_b.clone(),
+            incoming: b_to_a.clone(),
+        },
+        LinkedInfrared {
+            outgoing: b_to_a,
+            incoming: a_to_b,
+        },
+    )
+}
@@ -0,0 +1,76 @@
+// The eight keys of the console.
+#[derive(Clone, Copy)]
+pub enum Button {
+    Right,
+    Left,
+    Up,
+    Down,
+    A,
+    B,
+    Select,
+    Start,
+}
+
+impl Button {
+    fn mask(self) -> u8 {
+        1 << self as u8
+    }
+}
+
+// P1 at 0xFF00. Bits 4 and 5 select the direction keys and the buttons, and bits 0-3 read the
+// selected keys. Both are active low, and a key pulls its line low while either of its groups is
+// selected.
+pub struct Joypad {
+    select: u8,
+    // One bit per `Button`, set while it is held.
+    pressed: u8,
+}
+
+impl Joypad {
+    pub fn read(&self) -> u8 {
+        0xc0 | self.select | self.lines()
+    }
+
+    // Returns whether an input line went low, which requests the joypad interrupt.
+    pub fn write(&mut self, value: u8) -> bool {
+        let old_lines = self.lines();
+        self.select = value & 0x30;
+        old_lines & !self.lines() != 0
+    }
+
+    // Same as `write`.
+    pub fn set_button(&mut self, button: Button, pressed: bool) -> bool {
+        let old_lines = self.lines();
+        if pressed {
+            self.pressed |= button.mask();
+        } else {
+            self.pressed &= !button.mask();
+        }
+        old_lines & !self.lines() != 0
+    }
+
+    // Whether any input line is low, which is what brings the CPU out of STOP.
+    pub fn input_low(&self) -> bool {
+        self.lines() != 0x0f
+    }
+
+    fn lines(&self) -> u8 {
+        let mut low = 0;
+        if self.select & 0x10 == 0 {
+            low |= self.pressed & 0x0f;
+        }
+        if self.select & 0x20 == 0 {
+            low |= self.pressed >> 4;
+        }
+        !low & 0x0f
+    }
+}
+
+impl Default for Joypad {
+    fn default() -> Self {
+        Self {
+            select: 0x30,
+            pressed: 0,
+        }
+    }
+}
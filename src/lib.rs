@@ -0,0 +1,9 @@
+pub mod boot_rom;
+pub mod cartridge;
+pub mod cpu;
+pub mod error;
+pub mod infrared;
+pub mod joypad;
+pub mod model;
+pub mod save;
+mod timer;
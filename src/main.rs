@@ -1,5 +1,6 @@
-mod cpu;
+#[cfg(feature = "gui")]
 mod gui;
+#[cfg(feature = "gui")]
 mod ppu;
 
 const DMG_BOOT: [u8; 256] = [
@@ -21,19 +22,52 @@ const DMG_BOOT: [u8; 256] = [
     0x6, 0x19, 0x78, 0x86, 0x23, 0x5, 0x20, 0xfb, 0x86, 0x20, 0xfe, 0x3e, 0x1, 0xe0, 0x50,
 ];
 
+use gb_emulator::error::EmuError;
+
+// With a ROM path, runs it headless. Otherwise opens the GUI.
 fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let Some(path) = args.get(1) else {
+        present_gui();
+        return;
+    };
+    if let Err(error) = run(path, args.get(2)) {
+        eprintln!("{}", error);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(feature = "gui")]
+fn present_gui() {
     gui::present();
-/*    let args: Vec<String> = std::env::args().collect();
-    let contents = std::fs::read(&args[1])?;
+}
 
-    let mut cpu = cpu::CPU::default();
+// Built without the GUI, there is nothing to do without a ROM.
+#[cfg(not(feature = "gui"))]
+fn present_gui() {
+    eprintln!("usage: gb-emulator <rom> [boot rom]");
+    std::process::exit(2);
+}
+
+fn run(path: &str, boot_rom_path: Option<&String>) -> Result<(), EmuError> {
+    let contents = gb_emulator::error::read_file(path)?;
 
-    cpu.load_cartridge(&contents[0..65535]);
-    cpu.load_bootstrap(&DMG_BOOT);
+    let mut cpu = gb_emulator::cpu::CPU::default();
+
+    cpu.load_cartridge(&contents)?;
+    cpu.load_save_file(gb_emulator::save::SaveFile::for_rom(path))?;
+    match boot_rom_path {
+        Some(boot_rom_path) => cpu.load_bootstrap_file(boot_rom_path)?,
+        None => cpu.load_bootstrap(&DMG_BOOT)?,
+    }
 
     for _i in 1..10000000 {
-        cpu.step();
+        cpu.step()?;
+        if let Some(error) = cpu.take_save_error() {
+            return Err(error);
+        }
     }
+    cpu.flush_save()?;
 
-    Ok(())*/
+    Ok(())
 }
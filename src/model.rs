@@ -0,0 +1,35 @@
+// Hardware revisions that can be told apart by the state their boot ROMs leave behind.
+#[derive(Clone, Copy, PartialEq)]
+pub enum Model {
+    // Early original Game Boy.
+    DMG0,
+    DMG,
+    // Game Boy Pocket and Light.
+    MGB,
+    SGB,
+    SGB2,
+    CGB,
+    // Game Boy Advance running Game Boy software.
+    AGB,
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::CGB | Model::AGB)
+    }
+
+    pub fn is_sgb(self) -> bool {
+        matches!(self, Model::SGB | Model::SGB2)
+    }
+
+    // The internal counter behind DIV when the boot ROM hands over to the cartridge. The SGB boot
+    // ROM waits on the SNES, so its length, and this value, differ between boots.
+    pub fn post_boot_div_counter(self) -> u16 {
+        match self {
+            Model::DMG0 => 0x1830,
+            Model::DMG | Model::MGB => 0xabcc,
+            Model::SGB | Model::SGB2 => 0x0000,
+            Model::CGB | Model::AGB => 0x1ea0,
+        }
+    }
+}
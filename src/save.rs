@@ -0,0 +1,58 @@
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::error::EmuError;
+
+// A battery save: a raw dump of cartridge RAM, the same format other emulators and flash carts
+// use.
+pub struct SaveFile {
+    path: PathBuf,
+}
+
+impl SaveFile {
+    pub fn new(path: &str) -> SaveFile {
+        SaveFile {
+            path: PathBuf::from(path),
+        }
+    }
+
+    // The save next to the ROM, with its extension replaced by `.sav`.
+    pub fn for_rom(rom_path: &str) -> SaveFile {
+        SaveFile {
+            path: Path::new(rom_path).with_extension("sav"),
+        }
+    }
+
+    // Missing if nothing has been saved yet.
+    pub fn read(&self) -> Result<Option<Vec<u8>>, EmuError> {
+        match fs::read(&self.path) {
+            Ok(data) => Ok(Some(data)),
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(error) => Err(self.io_error(error)),
+        }
+    }
+
+    // Writes to a temporary file first and renames it over the save, so a crash halfway through
+    // leaves the previous save intact.
+    pub fn write(&self, data: &[u8]) -> Result<(), EmuError> {
+        let mut temporary_path = self.path.clone().into_os_string();
+        temporary_path.push(".tmp");
+        let temporary_path = PathBuf::from(temporary_path);
+
+        let result = File::create(&temporary_path).and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        });
+        result
+            .and_then(|()| fs::rename(&temporary_path, &self.path))
+            .map_err(|error| self.io_error(error))
+    }
+
+    fn io_error(&self, error: std::io::Error) -> EmuError {
+        EmuError::Io {
+            path: self.path.to_string_lossy().into_owned(),
+            error,
+        }
+    }
+}
@@ -0,0 +1,101 @@
+// DIV, TIMA, TMA and TAC at 0xFF04-0xFF07.
+//
+// DIV is the upper byte of a 16-bit counter that advances every T-cycle. TIMA increments on the
+// falling edge of the counter bit selected by TAC, so resetting DIV or changing TAC can also
+// increment it.
+#[derive(Default)]
+pub struct Timer {
+    counter: u16,
+    tima: u8,
+    tma: u8,
+    tac: u8,
+    // TIMA reads as 0 for one M-cycle after overflowing, before it is reloaded from TMA.
+    reload_pending: bool,
+}
+
+impl Timer {
+    // Advances the timer and returns whether the timer interrupt should be requested.
+    pub fn tick(&mut self, cycles: u32) -> bool {
+        let mut interrupt = false;
+        for _ in 0..cycles / 4 {
+            if self.reload_pending {
+                self.reload_pending = false;
+                self.tima = self.tma;
+                interrupt = true;
+            }
+
+            let old_input = self.input();
+            self.counter = self.counter.wrapping_add(4);
+            if old_input && !self.input() {
+                self.increment_tima();
+            }
+        }
+        interrupt
+    }
+
+    pub fn read_byte(&self, address: u16) -> u8 {
+        match address {
+            0xff04 => (self.counter >> 8) as u8,
+            0xff05 => self.tima,
+            0xff06 => self.tma,
+            0xff07 => self.tac | 0xf8,
+            _ => 0xff,
+        }
+    }
+
+    pub fn write_byte(&mut self, address: u16, value: u8) {
+        let old_input = self.input();
+        match address {
+            0xff04 => self.counter = 0,
+            0xff05 => {
+                // Writing TIMA while the reload is pending cancels it.
+                self.tima = value;
+                self.reload_pending = false;
+            }
+            0xff06 => self.tma = value,
+            0xff07 => self.tac = value & 0x7,
+            _ => {}
+        }
+        if old_input && !self.input() {
+            self.increment_tima();
+        }
+    }
+
+    // Sets the counter behind DIV without the side effects of writing to it.
+    pub fn set_counter(&mut self, counter: u16) {
+        self.counter = counter;
+    }
+
+    // T-cycles until the timer next requests an interrupt, if it is running.
+    pub fn cycles_until_interrupt(&self) -> Option<u32> {
+        if self.reload_pending {
+            return Some(4);
+        }
+        if self.tac & 0x4 == 0 {
+            return None;
+        }
+        // TIMA increments each time the counter crosses a multiple of twice the selected bit.
+        let period = 2u32 << self.input_bit();
+        let until_increment = period - (self.counter as u32 & (period - 1));
+        Some(until_increment + (0xff - self.tima as u32) * period + 4)
+    }
+
+    fn input(&self) -> bool {
+        self.tac & 0x4 != 0 && (self.counter >> self.input_bit()) & 1 != 0
+    }
+
+    fn input_bit(&self) -> u32 {
+        match self.tac & 0x3 {
+            0b00 => 9,
+            0b01 => 3,
+            0b10 => 5,
+            _ => 7,
+        }
+    }
+
+    fn increment_tima(&mut self) {
+        let (tima, did_overflow) = self.tima.overflowing_add(1);
+        self.tima = tima;
+        self.reload_pending = did_overflow;
+    }
+}